@@ -4,6 +4,7 @@
 //! insulin treatments that can be used for IOB calculations.
 
 use chrono::{DateTime, Utc};
+use crate::insulin::InsulinCurve;
 use crate::types::{Profile, Treatment};
 use crate::Result;
 
@@ -47,11 +48,15 @@ pub fn find_insulin_treatments(
             continue;
         }
 
-        // Handle bolus events
+        // Handle bolus events - decay analytically via the profile's
+        // insulin activity curve instead of handing back the raw dose
         if let Some(insulin) = event.insulin {
             if insulin > 0.0 {
+                let elapsed_minutes = (now_millis - event_date) as f64 / 60_000.0;
+                let iob = insulin * iob_fraction_remaining(profile, elapsed_minutes);
+
                 treatments.push(Treatment {
-                    insulin: Some(insulin),
+                    insulin: Some(iob),
                     date: event_date,
                     timestamp: event.timestamp.clone(),
                     started_at: event.started_at.clone().or_else(|| event.timestamp.clone()),
@@ -60,40 +65,33 @@ pub fn find_insulin_treatments(
             }
         }
 
-        // Handle temp basal events - convert to discrete insulin doses
+        // Handle temp basal events - integrate continuous delivery against
+        // the activity curve rather than chopping into pseudo-boluses
         if let (Some(rate), Some(duration)) = (event.rate, event.duration) {
             if duration > 0.0 {
-                // Get scheduled basal rate
-                let scheduled_basal = lookup_basal_at_time(profile, event_date);
-
-                // Calculate net insulin per 5-minute interval
-                let net_rate = rate - scheduled_basal;
-
-                // Split temp basal into 5-minute chunks
-                let chunks = (duration / 5.0).ceil() as i32;
+                // Round both sides to what the pump can actually deliver
+                // before taking the difference, so IOB reflects
+                // deliverable rates rather than the exact requested one
+                let scheduled_basal = round_basal(lookup_basal_at_time(profile, event_date), profile);
+                let net_rate = round_basal(rate, profile) - scheduled_basal;
 
-                for chunk in 0..chunks {
-                    let chunk_start = event_date + (chunk as i64 * 5 * 60 * 1000);
-
-                    // Don't add chunks in the future
-                    if chunk_start > now_millis {
-                        break;
-                    }
+                let full_end_millis = event_date + (duration * 60.0 * 1000.0) as i64;
+                let delivered_end_millis = full_end_millis.min(now_millis);
 
-                    // Calculate insulin for this 5-minute chunk
-                    let chunk_duration = if chunk == chunks - 1 {
-                        // Last chunk might be partial
-                        duration - (chunk as f64 * 5.0)
-                    } else {
-                        5.0
-                    };
+                if delivered_end_millis > event_date {
+                    let delivered_minutes = (delivered_end_millis - event_date) as f64 / 60_000.0;
+                    let net_units = net_rate * delivered_minutes / 60.0;
 
-                    let chunk_insulin = net_rate * chunk_duration / 60.0;
+                    let oldest_ago = (now_millis - event_date) as f64 / 60_000.0;
+                    let newest_ago = (now_millis - delivered_end_millis) as f64 / 60_000.0;
+                    let iob = net_units * average_iob_fraction(profile, oldest_ago, newest_ago);
 
-                    if chunk_insulin.abs() > 0.0001 {
+                    if iob.abs() > 0.0001 {
                         treatments.push(Treatment {
-                            insulin: Some(chunk_insulin),
-                            date: chunk_start,
+                            insulin: Some(iob),
+                            date: event_date,
+                            rate: event.rate,
+                            duration: event.duration,
                             ..Default::default()
                         });
                     }
@@ -126,16 +124,16 @@ pub fn find_insulin_treatments(
 }
 
 /// Look up the scheduled basal rate at a specific time
-fn lookup_basal_at_time(profile: &Profile, time_millis: i64) -> f64 {
+///
+/// `pub(crate)` so `meal::generate_dynamic_absorption` can net a temp
+/// basal's delivered rate against the schedule the same way this module
+/// does when converting pump history into insulin treatments.
+pub(crate) fn lookup_basal_at_time(profile: &Profile, time_millis: i64) -> f64 {
     if profile.basal_profile.is_empty() {
         return profile.current_basal;
     }
 
-    // Convert millis to datetime
-    let dt = DateTime::from_timestamp_millis(time_millis)
-        .unwrap_or_else(|| Utc::now());
-
-    let now_minutes = dt.hour() * 60 + dt.minute();
+    let now_minutes = local_minutes_of_day(profile, time_millis);
 
     // Sort schedule by index
     let mut schedule = profile.basal_profile.clone();
@@ -161,6 +159,194 @@ fn lookup_basal_at_time(profile: &Profile, time_millis: i64) -> f64 {
     rate
 }
 
+/// Round a basal rate to the pump-deliverable increment for `profile`.
+///
+/// Thin wrapper around `Profile::round_basal` so call sites in this
+/// module read as operating on "a rate, for a profile" alongside the
+/// other free functions here, rather than a method call buried in a
+/// longer expression.
+fn round_basal(rate: f64, profile: &Profile) -> f64 {
+    profile.round_basal(rate)
+}
+
+/// Fraction of a single dose's insulin still on board `t` minutes after
+/// delivery, selecting the activity curve from the profile's insulin
+/// model. Clamped to 1.0 before delivery and 0.0 past the DIA horizon.
+///
+/// `pub(crate)` so `meal::generate_dynamic_absorption` can derive the
+/// modeled insulin effect used to isolate the counteraction effect.
+pub(crate) fn iob_fraction_remaining(profile: &Profile, t: f64) -> f64 {
+    let td = profile.effective_dia() * 60.0;
+
+    if t <= 0.0 {
+        return 1.0;
+    }
+    if t >= td {
+        return 0.0;
+    }
+
+    match profile.curve {
+        InsulinCurve::Bilinear => {
+            bilinear_iob_fraction(t, td, profile.effective_peak_time() as f64)
+        }
+        InsulinCurve::RapidActing | InsulinCurve::UltraRapid => {
+            exponential_iob_fraction(t, td, profile.effective_peak_time() as f64)
+        }
+    }
+}
+
+/// Exponential (Loop-style) insulin activity model.
+///
+/// `td` and `tp` are DIA and peak time in minutes; `t` must be within
+/// `[0, td]`. See the Loop/oref0 `exponentialInsulinActivity` derivation.
+fn exponential_iob_fraction(t: f64, td: f64, tp: f64) -> f64 {
+    let tau = tp * (1.0 - tp / td) / (1.0 - 2.0 * tp / td);
+    let a = 2.0 * tau / td;
+    let s = 1.0 / (1.0 - a + (1.0 + a) * (-td / tau).exp());
+
+    (1.0 - s * (1.0 - a)
+        * (((t * t) / (tau * td * (1.0 - a)) - t / tau - 1.0) * (-t / tau).exp() + 1.0))
+        .clamp(0.0, 1.0)
+}
+
+/// Legacy Walsh bilinear insulin activity model: activity rises linearly
+/// to a peak at `tp` then falls linearly to zero at `td`, normalized so
+/// total delivered activity over `[0, td]` integrates to 1.
+fn bilinear_iob_fraction(t: f64, td: f64, tp: f64) -> f64 {
+    let peak_activity = 2.0 / td;
+
+    let cumulative = if t <= tp {
+        peak_activity * t * t / (2.0 * tp)
+    } else {
+        let at_peak = peak_activity * tp / 2.0;
+        at_peak + peak_activity / (td - tp) * (td * (t - tp) - (t * t - tp * tp) / 2.0)
+    };
+
+    (1.0 - cumulative).clamp(0.0, 1.0)
+}
+
+/// Average IOB fraction remaining across a continuous delivery window,
+/// via a fixed-step numerical integral (closed-form integration of the
+/// polynomial-times-exponential term is possible but unnecessary here).
+///
+/// `pub(crate)` so `meal::generate_dynamic_absorption` can integrate a
+/// temp basal's modeled insulin effect over its actual delivery window
+/// the same way this module does, rather than collapsing it to a single
+/// instant.
+pub(crate) fn average_iob_fraction(profile: &Profile, oldest_ago: f64, newest_ago: f64) -> f64 {
+    const STEPS: usize = 20;
+
+    let sum: f64 = (0..=STEPS)
+        .map(|i| {
+            let t = newest_ago + (oldest_ago - newest_ago) * (i as f64 / STEPS as f64);
+            iob_fraction_remaining(profile, t)
+        })
+        .sum();
+
+    sum / (STEPS as f64 + 1.0)
+}
+
+/// Resolve the minutes-from-midnight at `time_millis` in the profile's
+/// local wall-clock time (per `Profile::timezone`), so schedules defined
+/// in local time resolve to the right segment regardless of UTC offset.
+///
+/// `pub(crate)` so `profile::schedule::lookup_at` and the
+/// `basal_lookup`/`carb_ratio_lookup` callers of `CompiledSchedule::lookup`
+/// share this one timezone resolver rather than each profile-level
+/// schedule lookup computing minute-of-day straight off UTC.
+pub(crate) fn local_minutes_of_day(profile: &Profile, time_millis: i64) -> u32 {
+    let dt = DateTime::from_timestamp_millis(time_millis).unwrap_or_else(Utc::now);
+    let offset_minutes = resolve_offset_minutes(profile.timezone.as_deref(), dt);
+    let local = dt + chrono::Duration::minutes(offset_minutes);
+    local.hour() * 60 + local.minute()
+}
+
+/// Resolve a `Profile::timezone` string to a UTC offset in minutes at the
+/// given instant.
+///
+/// Only fixed offsets ("+02:00", "-0530", "UTC", "Z") are supported, per
+/// `Profile::timezone`'s contract -- IANA names aren't, since there's no
+/// tz database in this build to resolve them correctly across DST, and
+/// guessing would silently produce a wrong local time. Anything that
+/// isn't a recognized fixed offset (including an IANA name) resolves to
+/// UTC, same as when `timezone` is unset.
+fn resolve_offset_minutes(timezone: Option<&str>, _at: DateTime<Utc>) -> i64 {
+    match timezone {
+        Some(tz) => parse_fixed_offset_minutes(tz).unwrap_or(0),
+        None => 0,
+    }
+}
+
+/// Parse a fixed UTC offset like "+02:00", "-0530", "UTC", or "Z" into
+/// minutes east of UTC.
+fn parse_fixed_offset_minutes(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("UTC") || s.eq_ignore_ascii_case("Z") {
+        return Some(0);
+    }
+
+    let mut chars = s.chars();
+    let sign = match chars.next()? {
+        '+' => 1i64,
+        '-' => -1i64,
+        _ => return None,
+    };
+
+    let rest: String = chars.filter(|c| *c != ':').collect();
+    if rest.len() != 4 || !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let hours: i64 = rest[0..2].parse().ok()?;
+    let minutes: i64 = rest[2..4].parse().ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// Insulin scheduled to be delivered but not yet given.
+///
+/// Sums the net insulin still owed between `clock` and the end of the
+/// currently-running temp basal (walked in 5-minute sub-intervals against
+/// the scheduled rate in effect at each, since the schedule may change
+/// before the temp ends), plus any unconfirmed `pending_bolus`. Callers
+/// recommending a correction bolus subtract this so they don't double-count
+/// or stack insulin that hasn't actually reached the patient yet.
+pub fn pending_insulin(
+    profile: &Profile,
+    clock: DateTime<Utc>,
+    last_temp_basal: &Treatment,
+    pending_bolus: f64,
+) -> f64 {
+    let (rate, duration) = match (last_temp_basal.rate, last_temp_basal.duration) {
+        (Some(r), Some(d)) if d > 0.0 => (r, d),
+        _ => return pending_bolus.max(0.0),
+    };
+    // Round to what the pump can actually deliver, same as
+    // `find_insulin_treatments`, so this agrees with committed IOB about
+    // what's actually being delivered instead of the exact requested rate.
+    let rate = round_basal(rate, profile);
+
+    let start_millis = last_temp_basal.date;
+    let end_millis = start_millis + (duration * 60.0 * 1000.0) as i64;
+    let clock_millis = clock.timestamp_millis();
+
+    if end_millis <= clock_millis {
+        return pending_bolus.max(0.0);
+    }
+
+    let mut pending = 0.0;
+    let mut t = clock_millis.max(start_millis);
+
+    while t < end_millis {
+        let next = (t + 5 * 60 * 1000).min(end_millis);
+        let minutes_remaining = (next - t) as f64 / 60_000.0;
+        let scheduled = round_basal(lookup_basal_at_time(profile, t), profile);
+        pending += (rate - scheduled) * minutes_remaining / 60.0;
+        t = next;
+    }
+
+    pending + pending_bolus.max(0.0)
+}
+
 /// Split a temp basal that spans schedule changes
 ///
 /// This handles cases where a temp basal runs across midnight or
@@ -187,9 +373,7 @@ pub fn split_temp_basal_at_schedule_changes(
     let change_points: Vec<u32> = schedule.iter().map(|e| e.minutes).collect();
 
     let start_millis = treatment.date;
-    let start_dt = DateTime::from_timestamp_millis(start_millis)
-        .unwrap_or_else(Utc::now);
-    let start_minutes = start_dt.hour() * 60 + start_dt.minute();
+    let start_minutes = local_minutes_of_day(profile, start_millis);
 
     let end_minutes_from_start = duration as u32;
     let mut results = Vec::new();
@@ -272,8 +456,12 @@ mod tests {
 
         let treatments = find_insulin_treatments(&profile, &history, now, 0).unwrap();
 
+        // Insulin is now decayed analytically via the activity curve, so
+        // an hour-old bolus reports less than its raw dose but still most
+        // of it (DIA is 5h).
         assert_eq!(treatments.len(), 1);
-        assert_eq!(treatments[0].insulin, Some(2.0));
+        let iob = treatments[0].insulin.unwrap();
+        assert!(iob > 0.0 && iob < 2.0);
     }
 
     #[test]
@@ -297,19 +485,125 @@ mod tests {
         let profile = make_profile();
 
         // Temp basal of 2 U/hr for 30 min, scheduled basal is 1 U/hr
-        // Net rate is 1 U/hr, so 0.5 U total over 30 min
+        // Net rate is 1 U/hr, so 0.5 U delivered over 30 min, integrated
+        // continuously against the activity curve as a single treatment
         let history = vec![
             Treatment::temp_basal(2.0, 30.0, now - Duration::minutes(30)),
         ];
 
         let treatments = find_insulin_treatments(&profile, &history, now, 0).unwrap();
 
-        // Should be split into 6 chunks (30 min / 5 min)
-        assert!(treatments.len() >= 6);
+        assert_eq!(treatments.len(), 1);
+
+        // Barely decayed after 30 min on a 5h DIA, so close to but not
+        // over the raw 0.5 U delivered
+        let total = treatments[0].insulin.unwrap();
+        assert!(total > 0.0 && total <= 0.5);
+    }
+
+    #[test]
+    fn test_pending_insulin_from_running_temp() {
+        let now = Utc::now();
+        let profile = make_profile(); // current_basal 1.0 U/hr, empty schedule
+
+        // Temp of 2 U/hr for 20 more minutes, started 10 min ago
+        let temp = Treatment::temp_basal(2.0, 30.0, now - Duration::minutes(10));
+
+        // Net 1 U/hr over the remaining 20 min = 1/3 U, plus a 0.2 U pending bolus
+        let pending = pending_insulin(&profile, now, &temp, 0.2);
+        assert!((pending - (1.0 / 3.0 + 0.2)).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_pending_insulin_temp_already_ended() {
+        let now = Utc::now();
+        let profile = make_profile();
+
+        let temp = Treatment::temp_basal(2.0, 30.0, now - Duration::minutes(60));
 
-        // Each chunk should have ~0.083 U (1 U/hr * 5/60 hr)
-        let total: f64 = treatments.iter().map(|t| t.insulin.unwrap_or(0.0)).sum();
-        assert!((total - 0.5).abs() < 0.01);
+        let pending = pending_insulin(&profile, now, &temp, 0.1);
+        assert!((pending - 0.1).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_pending_insulin_no_temp() {
+        let now = Utc::now();
+        let profile = make_profile();
+
+        let bolus_only = Treatment::bolus(1.0, now);
+        let pending = pending_insulin(&profile, now, &bolus_only, 0.3);
+        assert!((pending - 0.3).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_pending_insulin_rounds_net_rate_like_find_insulin_treatments() {
+        let now = Utc::now();
+        let profile = make_profile(); // current_basal 1.0 U/hr, flat 0.05 U/hr increment
+
+        // 2.03 U/hr isn't a deliverable increment; the pump actually delivers
+        // 2.05. If this weren't rounded, net rate would be 1.03 U/hr instead
+        // of the rounded 1.05 U/hr, changing the 20-minutes-remaining result.
+        let temp = Treatment::temp_basal(2.03, 30.0, now - Duration::minutes(10));
+
+        let pending = pending_insulin(&profile, now, &temp, 0.0);
+        assert!((pending - (1.05 / 3.0)).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_temp_basal_uses_pump_rounded_rates() {
+        let now = Utc::now();
+
+        // x23/x54 models round to 0.025 U/hr below 1 U/hr: a 0.013 U/hr
+        // temp against a 0.0 scheduled basal should round up to 0.025,
+        // not vanish as negligible
+        let profile = Profile {
+            model: Some("523".to_string()),
+            current_basal: 0.0,
+            dia: 5.0,
+            ..Default::default()
+        };
+
+        let history = vec![
+            Treatment::temp_basal(0.013, 30.0, now - Duration::minutes(30)),
+        ];
+
+        let treatments = find_insulin_treatments(&profile, &history, now, 0).unwrap();
+
+        assert_eq!(treatments.len(), 1);
+        assert!(treatments[0].insulin.unwrap() > 0.0);
+    }
+
+    #[test]
+    fn test_iob_fraction_clamped_outside_dia_window() {
+        let profile = make_profile();
+
+        assert_eq!(iob_fraction_remaining(&profile, 0.0), 1.0);
+        assert_eq!(iob_fraction_remaining(&profile, -5.0), 1.0);
+        assert_eq!(iob_fraction_remaining(&profile, profile.effective_dia() * 60.0), 0.0);
+        assert_eq!(iob_fraction_remaining(&profile, 10_000.0), 0.0);
+    }
+
+    #[test]
+    fn test_iob_fraction_decays_monotonically() {
+        let profile = make_profile();
+
+        let early = iob_fraction_remaining(&profile, 30.0);
+        let later = iob_fraction_remaining(&profile, 120.0);
+        assert!(early > later);
+    }
+
+    #[test]
+    fn test_bilinear_curve_used_for_legacy_model() {
+        use crate::insulin::InsulinCurve;
+
+        let profile = Profile {
+            curve: InsulinCurve::Bilinear,
+            dia: 4.0,
+            ..Default::default()
+        };
+
+        let iob = iob_fraction_remaining(&profile, 60.0);
+        assert!(iob > 0.0 && iob < 1.0);
     }
 
     #[test]
@@ -341,6 +635,53 @@ mod tests {
         assert_eq!(result[0].duration, Some(60.0));
     }
 
+    #[test]
+    fn test_basal_lookup_honors_profile_timezone() {
+        use chrono::TimeZone;
+        use crate::types::BasalScheduleEntry;
+
+        // 01:00 UTC is 06:00 local at +05:00, which should pick up the
+        // schedule entry that starts at 06:00 local
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 1, 0, 0).unwrap();
+
+        let profile = Profile {
+            current_basal: 1.0,
+            timezone: Some("+05:00".to_string()),
+            basal_profile: vec![
+                BasalScheduleEntry::new(0, 0.8, 0),   // 00:00 local
+                BasalScheduleEntry::new(1, 1.0, 360), // 06:00 local
+            ],
+            ..Default::default()
+        };
+
+        let rate = lookup_basal_at_time(&profile, time.timestamp_millis());
+        assert!((rate - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_basal_lookup_treats_unsupported_iana_timezone_as_utc() {
+        use chrono::TimeZone;
+        use crate::types::BasalScheduleEntry;
+
+        // No tz database in this build to resolve an IANA name's
+        // DST-aware offset, so it must fall back to UTC rather than
+        // silently applying some other offset.
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 1, 0, 0).unwrap();
+
+        let profile = Profile {
+            current_basal: 1.0,
+            timezone: Some("America/New_York".to_string()),
+            basal_profile: vec![
+                BasalScheduleEntry::new(0, 0.8, 0),   // 00:00
+                BasalScheduleEntry::new(1, 1.0, 360), // 06:00
+            ],
+            ..Default::default()
+        };
+
+        let rate = lookup_basal_at_time(&profile, time.timestamp_millis());
+        assert!((rate - 0.8).abs() < 0.001);
+    }
+
     #[test]
     fn test_split_temp_basal_with_schedule() {
         use chrono::TimeZone;
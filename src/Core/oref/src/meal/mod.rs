@@ -4,11 +4,19 @@ use chrono::{DateTime, Utc};
 use crate::types::{MealData, Profile, Treatment, GlucoseReading};
 use crate::cob;
 use crate::Result;
+use crate::profile::carbs::carb_ratio_lookup;
+use crate::profile::isf::isf_lookup;
+use crate::iob::history::{average_iob_fraction, iob_fraction_remaining, lookup_basal_at_time};
 
 /// Generate meal data from treatment history
 ///
 /// This implements the meal detection from `lib/meal/index.js` and `lib/meal/total.js`.
-/// COB is calculated using glucose deviation analysis from the cob module.
+/// COB is calculated using glucose deviation analysis from the cob module. `cob::calculate`
+/// only accepts a single `Profile` snapshot, so rather than the profile's scalar
+/// `carb_ratio`/`sens` at `clock`, we resolve the carb-weighted average of the carb ratio
+/// and ISF actually in effect at each qualifying carb entry's own timestamp (via
+/// `crate::profile::carb_ratio_lookup`/`isf_lookup`, see `profile::schedule`) and pass
+/// `cob::calculate` a profile carrying those resolved values instead.
 pub fn generate(
     profile: &Profile,
     treatments: &[Treatment],
@@ -26,6 +34,8 @@ pub fn generate(
     let mut journal_carbs = 0.0;
     let mut last_carb_time: i64 = 0;
     let mut bw_found = false;
+    let mut carb_ratio_weighted = 0.0;
+    let mut isf_weighted = 0.0;
 
     for treatment in treatments {
         let treatment_time = treatment.effective_date();
@@ -39,6 +49,10 @@ pub fn generate(
                 carbs += c;
                 last_carb_time = last_carb_time.max(treatment_time);
 
+                let entry_clock = DateTime::from_timestamp_millis(treatment_time).unwrap_or(clock);
+                carb_ratio_weighted += carb_ratio_lookup(profile, entry_clock) * c;
+                isf_weighted += isf_lookup(profile, entry_clock) * c;
+
                 // Categorize carb source
                 if let Some(ns) = treatment.ns_carbs {
                     ns_carbs += ns;
@@ -55,8 +69,21 @@ pub fn generate(
         }
     }
 
+    // Carb-weighted average of the carb ratio/ISF actually in effect when
+    // each entry was logged, rather than the profile's scalar snapshot at
+    // `clock`. Falls back to the plain profile when there are no entries.
+    let entry_resolved_profile = if carbs > 0.0 {
+        Profile {
+            carb_ratio: carb_ratio_weighted / carbs,
+            sens: isf_weighted / carbs,
+            ..profile.clone()
+        }
+    } else {
+        profile.clone()
+    };
+
     // Calculate COB using glucose deviation analysis
-    let cob_result = cob::calculate(profile, glucose_data, treatments, clock)?;
+    let cob_result = cob::calculate(&entry_resolved_profile, glucose_data, treatments, clock)?;
 
     // Use deviation-based COB, but cap at max_cob and entered carbs
     let meal_cob = cob_result.meal_cob.min(profile.max_cob).min(carbs);
@@ -98,6 +125,188 @@ pub fn find_meals(
         .collect()
 }
 
+/// A carb entry with its own absorption window, for dynamic
+/// (counteraction-driven) absorption tracking.
+///
+/// `Treatment` carries raw carb fields but no per-entry absorption time,
+/// so dynamic-mode callers describe each entry separately here.
+#[derive(Debug, Clone)]
+pub struct DynamicCarbEntry {
+    /// When the carbs were entered (millis since epoch)
+    pub date: i64,
+    /// Grams of carbohydrate in this entry
+    pub carbs: f64,
+    /// This entry's own absorption window, in hours
+    pub absorption_time: f64,
+}
+
+/// An entry's absorption state at the evaluation clock
+#[derive(Debug, Clone)]
+pub struct CarbAbsorptionState {
+    pub date: i64,
+    pub carbs: f64,
+    pub absorbed: f64,
+    pub remaining: f64,
+}
+
+/// Result of dynamic carb absorption tracking: per-entry timelines plus
+/// the aggregate carbs-on-board they imply
+#[derive(Debug, Clone)]
+pub struct DynamicAbsorptionResult {
+    pub entries: Vec<CarbAbsorptionState>,
+    pub meal_cob: f64,
+}
+
+/// Minimum grams a five-minute interval is assumed to absorb from the
+/// oldest outstanding entry, so COB doesn't stall indefinitely when the
+/// counteraction signal is near zero
+const MIN_TRICKLE_GRAMS_PER_5M: f64 = 0.1;
+
+/// Track carb absorption dynamically from observed insulin counteraction
+/// effects, instead of a single fixed absorption window per meal.
+///
+/// Walks glucose readings in order. Each interval's counteraction effect
+/// (the observed BG delta minus the modeled insulin effect, derived from
+/// `insulin_treatments` via the same exponential/bilinear activity curve
+/// used for IOB) is converted to grams using the carb ratio and ISF in
+/// effect at that time, then absorbed from the oldest entry that's still
+/// outstanding at that point. Absorption is clamped so an entry never
+/// absorbs more than it still contains, nor less than a minimum trickle
+/// rate, and entries not yet entered at a given interval are skipped.
+///
+/// `insulin_treatments` must be raw pump/treatment history (boluses with
+/// `.insulin` as the delivered dose, temp basals with `.rate`/`.duration`)
+/// -- NOT `find_insulin_treatments`'s output, which already reports IOB
+/// remaining at its own `clock` rather than a dose, and would be decayed
+/// a second time here.
+pub fn generate_dynamic_absorption(
+    profile: &Profile,
+    carb_entries: &[DynamicCarbEntry],
+    insulin_treatments: &[Treatment],
+    glucose_data: &[GlucoseReading],
+    clock: DateTime<Utc>,
+) -> DynamicAbsorptionResult {
+    let mut states: Vec<CarbAbsorptionState> = carb_entries
+        .iter()
+        .map(|entry| CarbAbsorptionState {
+            date: entry.date,
+            carbs: entry.carbs,
+            absorbed: 0.0,
+            remaining: entry.carbs,
+        })
+        .collect();
+    states.sort_by_key(|s| s.date);
+
+    let mut readings: Vec<&GlucoseReading> = glucose_data.iter().collect();
+    readings.sort_by_key(|g| g.date);
+
+    let clock_millis = clock.timestamp_millis();
+
+    for window in readings.windows(2) {
+        let (prev, next) = (window[0], window[1]);
+
+        if next.date <= prev.date || next.date > clock_millis {
+            continue;
+        }
+
+        let minutes = (next.date - prev.date) as f64 / 60_000.0;
+        let at = DateTime::from_timestamp_millis(next.date).unwrap_or(clock);
+        let isf = isf_lookup(profile, at);
+        let carb_ratio = carb_ratio_lookup(profile, at);
+
+        let observed_delta = next.sgv - prev.sgv;
+        let insulin_effect = modeled_insulin_effect(profile, insulin_treatments, prev.date, next.date, isf);
+        let counteraction = observed_delta - insulin_effect;
+
+        let grams = (counteraction / isf * carb_ratio).max(0.0);
+        let min_trickle = MIN_TRICKLE_GRAMS_PER_5M * (minutes / 5.0);
+
+        if let Some(active) = states
+            .iter_mut()
+            .find(|s| s.date <= next.date && s.remaining > 0.0001)
+        {
+            let absorbed_now = grams.max(min_trickle).min(active.remaining);
+            active.absorbed += absorbed_now;
+            active.remaining -= absorbed_now;
+        }
+    }
+
+    let meal_cob = states.iter().map(|s| s.remaining).sum();
+
+    DynamicAbsorptionResult { entries: states, meal_cob }
+}
+
+/// BG effect modeled from currently-active insulin over `[interval_start,
+/// interval_end)`, using the fraction of each dose's IOB curve that
+/// activates during the interval. Negative, since insulin lowers BG.
+fn modeled_insulin_effect(
+    profile: &Profile,
+    insulin_treatments: &[Treatment],
+    interval_start: i64,
+    interval_end: i64,
+    isf: f64,
+) -> f64 {
+    insulin_treatments
+        .iter()
+        .map(|t| treatment_insulin_effect(profile, t, interval_start, interval_end, isf))
+        .sum()
+}
+
+/// A single raw treatment's contribution to `modeled_insulin_effect`.
+///
+/// A bolus decays from its own `.insulin` dose at `effective_date()`. A
+/// temp basal has no `.insulin` field, so it's netted against the
+/// schedule the same way `find_insulin_treatments` does, and its
+/// continuous delivery over `[event_start, event_end)` is integrated the
+/// same way too (`average_iob_fraction`), rather than collapsed to a
+/// single instant -- the activity curve isn't linear enough around its
+/// peak for a midpoint approximation to hold up over anything but a
+/// short temp basal.
+fn treatment_insulin_effect(
+    profile: &Profile,
+    t: &Treatment,
+    interval_start: i64,
+    interval_end: i64,
+    isf: f64,
+) -> f64 {
+    if let Some(dose) = t.insulin {
+        let dose_date = t.effective_date();
+        let elapsed_start = (interval_start - dose_date) as f64 / 60_000.0;
+        let elapsed_end = (interval_end - dose_date) as f64 / 60_000.0;
+        let activated = iob_fraction_remaining(profile, elapsed_start)
+            - iob_fraction_remaining(profile, elapsed_end);
+        return -dose * activated * isf;
+    }
+
+    let (Some(rate), Some(duration)) = (t.rate, t.duration) else {
+        return 0.0;
+    };
+    if duration <= 0.0 {
+        return 0.0;
+    }
+
+    let event_start = t.effective_date();
+    let event_end = event_start + (duration * 60.0 * 1000.0) as i64;
+
+    let scheduled_basal = profile.round_basal(lookup_basal_at_time(profile, event_start));
+    let net_rate = profile.round_basal(rate) - scheduled_basal;
+    let net_units = net_rate * duration / 60.0;
+
+    // Average IOB fraction remaining (at `interval_start` and at
+    // `interval_end`) across the dose's own continuous delivery window,
+    // mirroring how `find_insulin_treatments` averages it across the
+    // same window relative to a single "now".
+    let avg_fraction_remaining_at = |reference: i64| {
+        let oldest_ago = (reference - event_start) as f64 / 60_000.0;
+        let newest_ago = (reference - event_end) as f64 / 60_000.0;
+        average_iob_fraction(profile, oldest_ago, newest_ago)
+    };
+    let activated =
+        avg_fraction_remaining_at(interval_start) - avg_fraction_remaining_at(interval_end);
+
+    -net_units * activated * isf
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -170,4 +379,157 @@ mod tests {
 
         assert!(meal_data.meal_cob <= 100.0);
     }
+
+    #[test]
+    fn test_dynamic_absorption_decrements_remaining_cob() {
+        let now = Utc::now();
+        let profile = make_profile();
+        let entry_time = now - Duration::hours(1);
+
+        let carb_entries = vec![DynamicCarbEntry {
+            date: entry_time.timestamp_millis(),
+            carbs: 40.0,
+            absorption_time: 3.0,
+        }];
+
+        // A steady rise well beyond what's explained by (zero) insulin
+        // should be attributed to the outstanding carb entry.
+        let glucose_data = vec![
+            GlucoseReading { date: entry_time.timestamp_millis(), sgv: 100.0 },
+            GlucoseReading { date: (entry_time + Duration::minutes(5)).timestamp_millis(), sgv: 110.0 },
+            GlucoseReading { date: (entry_time + Duration::minutes(10)).timestamp_millis(), sgv: 120.0 },
+        ];
+
+        let result = generate_dynamic_absorption(&profile, &carb_entries, &[], &glucose_data, now);
+
+        assert!(result.meal_cob < 40.0);
+        assert!(result.meal_cob >= 0.0);
+        assert_eq!(result.entries[0].absorbed + result.entries[0].remaining, 40.0);
+    }
+
+    #[test]
+    fn test_dynamic_absorption_never_exceeds_entry_carbs() {
+        let now = Utc::now();
+        let profile = make_profile();
+        let entry_time = now - Duration::hours(1);
+
+        let carb_entries = vec![DynamicCarbEntry {
+            date: entry_time.timestamp_millis(),
+            carbs: 10.0,
+            absorption_time: 3.0,
+        }];
+
+        // A huge implausible spike shouldn't absorb more carbs than entered.
+        let glucose_data = vec![
+            GlucoseReading { date: entry_time.timestamp_millis(), sgv: 100.0 },
+            GlucoseReading { date: (entry_time + Duration::minutes(5)).timestamp_millis(), sgv: 300.0 },
+        ];
+
+        let result = generate_dynamic_absorption(&profile, &carb_entries, &[], &glucose_data, now);
+
+        assert!(result.entries[0].absorbed <= 10.0);
+        assert!(result.entries[0].remaining >= 0.0);
+    }
+
+    #[test]
+    fn test_dynamic_absorption_ignores_entries_not_yet_entered() {
+        let now = Utc::now();
+        let profile = make_profile();
+
+        // Carbs entered after the glucose window started shouldn't absorb
+        // from readings that predate them.
+        let carb_entries = vec![DynamicCarbEntry {
+            date: now.timestamp_millis(),
+            carbs: 20.0,
+            absorption_time: 3.0,
+        }];
+
+        let glucose_data = vec![
+            GlucoseReading { date: (now - Duration::minutes(10)).timestamp_millis(), sgv: 100.0 },
+            GlucoseReading { date: (now - Duration::minutes(5)).timestamp_millis(), sgv: 105.0 },
+        ];
+
+        let result = generate_dynamic_absorption(&profile, &carb_entries, &[], &glucose_data, now);
+
+        assert_eq!(result.entries[0].absorbed, 0.0);
+        assert_eq!(result.meal_cob, 20.0);
+    }
+
+    #[test]
+    fn test_modeled_insulin_effect_decays_raw_bolus_dose() {
+        let profile = make_profile();
+        let now = Utc::now();
+        let dose_time = now - Duration::minutes(30);
+
+        let insulin_treatments = vec![Treatment::bolus(2.0, dose_time)];
+
+        let effect = modeled_insulin_effect(
+            &profile,
+            &insulin_treatments,
+            dose_time.timestamp_millis(),
+            now.timestamp_millis(),
+            profile.sens,
+        );
+
+        // A bolus delivered just before the interval should still be
+        // actively lowering BG -- never dropped, never zero.
+        assert!(effect < 0.0);
+    }
+
+    #[test]
+    fn test_modeled_insulin_effect_nets_temp_basal_against_schedule() {
+        let profile = make_profile();
+        let now = Utc::now();
+        let temp_start = now - Duration::minutes(30);
+
+        // current_basal defaults to 1.0 U/h, so a 2.0 U/h temp basal
+        // delivers 1.0 U/h above schedule -- this must show up here even
+        // though a temp basal has no `.insulin` field of its own.
+        let insulin_treatments = vec![Treatment::temp_basal(2.0, 30.0, temp_start)];
+
+        let effect = modeled_insulin_effect(
+            &profile,
+            &insulin_treatments,
+            temp_start.timestamp_millis(),
+            now.timestamp_millis(),
+            profile.sens,
+        );
+
+        assert!(effect < 0.0);
+    }
+
+    #[test]
+    fn test_dynamic_absorption_attributes_more_to_carbs_when_insulin_is_active() {
+        let now = Utc::now();
+        let profile = make_profile();
+        let entry_time = now - Duration::hours(1);
+
+        let carb_entries = vec![DynamicCarbEntry {
+            date: entry_time.timestamp_millis(),
+            carbs: 40.0,
+            absorption_time: 3.0,
+        }];
+
+        let glucose_data = vec![
+            GlucoseReading { date: entry_time.timestamp_millis(), sgv: 100.0 },
+            GlucoseReading { date: (entry_time + Duration::minutes(5)).timestamp_millis(), sgv: 110.0 },
+            GlucoseReading { date: (entry_time + Duration::minutes(10)).timestamp_millis(), sgv: 120.0 },
+        ];
+
+        let without_insulin =
+            generate_dynamic_absorption(&profile, &carb_entries, &[], &glucose_data, now);
+
+        let insulin_treatments = vec![Treatment::bolus(5.0, entry_time)];
+        let with_insulin = generate_dynamic_absorption(
+            &profile,
+            &carb_entries,
+            &insulin_treatments,
+            &glucose_data,
+            now,
+        );
+
+        // Active insulin would have lowered BG, so the same observed rise
+        // implies a larger counteraction effect and more carbs absorbed.
+        assert!(with_insulin.meal_cob < without_insulin.meal_cob);
+    }
 }
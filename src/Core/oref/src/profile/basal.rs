@@ -1,50 +1,143 @@
 //! Basal rate schedule lookups
 
-use chrono::{DateTime, Timelike, Utc};
-use crate::types::Profile;
+use chrono::{DateTime, Datelike, Utc};
+use crate::iob::history::local_minutes_of_day;
+use crate::profile::schedule::CompiledSchedule;
+use crate::profile::{overrides, solar};
+use crate::types::{BasalScheduleEntry, Profile};
 
-/// Look up the basal rate at a specific time
+/// Look up the basal rate at a specific time, using the weekday-specific
+/// schedule mapped for `time`'s weekday when the profile defines one,
+/// falling back to the default `basal_profile` otherwise. Any
+/// solar-anchored entries are resolved against `time`'s date first, and
+/// any active recurring `schedule_overrides` entry takes precedence over
+/// the resulting scheduled rate.
+///
+/// This is a thin wrapper around [`compile_basal_schedule`] for one-off
+/// lookups; callers evaluating many timestamps within the same day or
+/// week should compile once and reuse the result instead.
 pub fn basal_lookup(profile: &Profile, time: DateTime<Utc>) -> f64 {
-    if profile.basal_profile.is_empty() {
+    let compiled = compile_basal_schedule(profile, time);
+    let minute_of_day = local_minutes_of_day(profile, time.timestamp_millis());
+    let scheduled = compiled.lookup(minute_of_day);
+
+    let rate = overrides::active_override(&profile.schedule_overrides, time)
+        .and_then(|o| o.apply_basal(scheduled, time))
+        .unwrap_or(scheduled);
+
+    (rate * 1000.0).round() / 1000.0
+}
+
+/// Compile the basal schedule active on `time`'s weekday -- with solar
+/// anchors resolved against `time`'s date -- into a [`CompiledSchedule`],
+/// so a simulation or replay loop evaluating many timestamps across that
+/// same day can look each one up with `CompiledSchedule::lookup` instead
+/// of re-sorting the schedule on every call the way `basal_lookup` does.
+/// Recurring `schedule_overrides` still need to be applied per timestamp
+/// by the caller, since they aren't a function of minute-of-day alone.
+pub fn compile_basal_schedule(profile: &Profile, time: DateTime<Utc>) -> CompiledSchedule {
+    let entries = weekday_basal_profile(profile, time);
+    let resolved = resolve_solar_entries(profile, entries, time);
+    CompiledSchedule::compile(&resolved, profile.current_basal, |e| e.rate)
+}
+
+/// Get the maximum daily basal rate from the schedule active on `time`'s
+/// weekday, using the weekday-specific schedule when the profile defines
+/// one, falling back to the default `basal_profile` otherwise.
+pub fn max_daily_basal(profile: &Profile, time: DateTime<Utc>) -> f64 {
+    let entries = weekday_basal_profile(profile, time);
+
+    if entries.is_empty() {
         return profile.current_basal;
     }
 
-    let now_minutes = time.hour() * 60 + time.minute();
-
-    // Sort by index
-    let mut schedule: Vec<_> = profile.basal_profile.iter().collect();
-    schedule.sort_by_key(|e| e.i);
+    entries
+        .iter()
+        .map(|e| e.rate)
+        .fold(0.0_f64, |a, b| a.max(b))
+}
 
-    // Default to last entry
-    let mut rate = schedule.last().map(|e| e.rate).unwrap_or(profile.current_basal);
+/// Get the total daily basal insulin (units) by integrating the schedule
+/// active on `time`'s weekday over 24 hours: each segment's `rate`
+/// multiplied by its duration in hours, summed. An empty schedule
+/// integrates to `current_basal` over the full day.
+pub fn total_daily_basal(profile: &Profile, time: DateTime<Utc>) -> f64 {
+    basal_segments(profile, time)
+        .iter()
+        .map(|&(start, end, rate)| rate * (end - start) as f64 / 60.0)
+        .sum()
+}
 
-    for i in 0..schedule.len() {
-        let entry = schedule[i];
-        let next_minutes = if i + 1 < schedule.len() {
-            schedule[i + 1].minutes
-        } else {
-            24 * 60
-        };
+/// Normalize the basal schedule active on `time`'s weekday into
+/// `(start_minute, end_minute, rate)` segments: solar anchors resolved,
+/// sorted into schedule order, each segment running to the next entry's
+/// `minutes` with the last wrapping to midnight (1440), and `rate`
+/// rounded the same way `basal_lookup` rounds it. Lets callers chart or
+/// aggregate the day's distribution without re-deriving the windowing
+/// that `basal_lookup` already does internally.
+pub fn basal_segments(profile: &Profile, time: DateTime<Utc>) -> Vec<(u32, u32, f64)> {
+    let entries = weekday_basal_profile(profile, time);
+    let mut resolved = resolve_solar_entries(profile, entries, time);
 
-        if now_minutes >= entry.minutes && now_minutes < next_minutes {
-            rate = entry.rate;
-            break;
-        }
+    if resolved.is_empty() {
+        return vec![(0, 1440, round_rate(profile.current_basal))];
     }
 
+    resolved.sort_by_key(|e| e.i);
+
+    resolved
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let end = if i + 1 < resolved.len() {
+                resolved[i + 1].minutes
+            } else {
+                1440
+            };
+            (entry.minutes, end, round_rate(entry.rate))
+        })
+        .collect()
+}
+
+fn round_rate(rate: f64) -> f64 {
     (rate * 1000.0).round() / 1000.0
 }
 
-/// Get the maximum daily basal rate from the schedule
-pub fn max_daily_basal(profile: &Profile) -> f64 {
-    if profile.basal_profile.is_empty() {
-        return profile.current_basal;
-    }
+/// The basal schedule in effect on `time`'s weekday: the weekday-specific
+/// schedule when one is mapped, otherwise the profile's default.
+fn weekday_basal_profile(profile: &Profile, time: DateTime<Utc>) -> &Vec<BasalScheduleEntry> {
+    profile
+        .weekday_schedules
+        .as_ref()
+        .and_then(|schedules| schedules.for_weekday(time.weekday()))
+        .map(|day| &day.basal_profile)
+        .unwrap_or(&profile.basal_profile)
+}
 
-    profile.basal_profile
+/// Resolve any `solar_anchor` boundaries in `entries` against `time`'s
+/// date and the profile's `latitude`/`longitude`, falling back to the
+/// entry's plain `minutes` when the profile has no location set or the
+/// event doesn't occur that day (polar day/night). Entries without a
+/// solar anchor are returned unchanged.
+fn resolve_solar_entries(
+    profile: &Profile,
+    entries: &[BasalScheduleEntry],
+    time: DateTime<Utc>,
+) -> Vec<BasalScheduleEntry> {
+    entries
         .iter()
-        .map(|e| e.rate)
-        .fold(0.0_f64, |a, b| a.max(b))
+        .map(|entry| {
+            let mut resolved = entry.clone();
+            if let Some(anchor) = &entry.solar_anchor {
+                if let (Some(lat), Some(lon)) = (profile.latitude, profile.longitude) {
+                    if let Some(minutes) = solar::resolve_minutes_of_day(anchor, lat, lon, time) {
+                        resolved.minutes = minutes.clamp(0, 1439);
+                    }
+                }
+            }
+            resolved
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -96,11 +189,43 @@ mod tests {
     #[test]
     fn test_max_daily_basal() {
         let profile = make_profile_with_schedule();
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
 
-        let max = max_daily_basal(&profile);
+        let max = max_daily_basal(&profile, time);
         assert!((max - 1.2).abs() < 0.001);
     }
 
+    #[test]
+    fn test_weekday_schedule_overrides_default() {
+        use crate::types::{DaySchedule, WeekdaySchedules};
+        use std::collections::HashMap;
+
+        let mut profile = make_profile_with_schedule();
+        // Saturday, 2024-01-06
+        let saturday = Utc.with_ymd_and_hms(2024, 1, 6, 8, 0, 0).unwrap();
+
+        let weekend_schedule = DaySchedule {
+            basal_profile: vec![BasalScheduleEntry::new(0, 0.4, 0)],
+            carb_ratio_profile: vec![],
+        };
+
+        let mut schedules = HashMap::new();
+        schedules.insert("weekend".to_string(), weekend_schedule);
+        let mut weekday_schedule = HashMap::new();
+        weekday_schedule.insert(5, "weekend".to_string()); // Saturday
+        weekday_schedule.insert(6, "weekend".to_string()); // Sunday
+
+        profile.weekday_schedules = Some(WeekdaySchedules { schedules, weekday_schedule });
+
+        // Saturday uses the weekend schedule, flat 0.4 all day
+        assert!((basal_lookup(&profile, saturday) - 0.4).abs() < 0.001);
+        assert!((max_daily_basal(&profile, saturday) - 0.4).abs() < 0.001);
+
+        // A weekday with no mapping still uses the default schedule
+        let monday = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
+        assert!((basal_lookup(&profile, monday) - 1.0).abs() < 0.001);
+    }
+
     #[test]
     fn test_empty_schedule_uses_current() {
         let profile = Profile {
@@ -112,4 +237,174 @@ mod tests {
         let rate = basal_lookup(&profile, Utc::now());
         assert!((rate - 0.75).abs() < 0.001);
     }
+
+    #[test]
+    fn test_solar_anchored_entry_tracks_sunrise() {
+        use crate::profile::solar::{SolarAnchor, SolarEvent};
+
+        let profile = Profile {
+            current_basal: 0.5,
+            latitude: Some(0.0),
+            longitude: Some(0.0),
+            basal_profile: vec![
+                BasalScheduleEntry::new(0, 0.8, 0),
+                BasalScheduleEntry::with_solar_anchor(
+                    1,
+                    1.2,
+                    SolarAnchor { event: SolarEvent::Sunrise, offset_minutes: 0 },
+                    360,
+                ),
+            ],
+            ..Default::default()
+        };
+
+        // Equinox at the equator: sunrise lands near 06:00 UTC, so 06:30
+        // should already be in the "dawn" segment.
+        let time = Utc.with_ymd_and_hms(2024, 3, 20, 6, 30, 0).unwrap();
+        assert!((basal_lookup(&profile, time) - 1.2).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_solar_anchored_entry_falls_back_without_location() {
+        use crate::profile::solar::{SolarAnchor, SolarEvent};
+
+        let profile = Profile {
+            current_basal: 0.5,
+            latitude: None,
+            longitude: None,
+            basal_profile: vec![
+                BasalScheduleEntry::new(0, 0.8, 0),
+                BasalScheduleEntry::with_solar_anchor(
+                    1,
+                    1.2,
+                    SolarAnchor { event: SolarEvent::Sunrise, offset_minutes: 0 },
+                    360,
+                ),
+            ],
+            ..Default::default()
+        };
+
+        // No lat/long set, so the fallback minute-of-day (06:00) applies
+        let time = Utc.with_ymd_and_hms(2024, 3, 20, 6, 30, 0).unwrap();
+        assert!((basal_lookup(&profile, time) - 1.2).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_solar_anchored_entry_falls_back_on_polar_night() {
+        use crate::profile::solar::{SolarAnchor, SolarEvent};
+
+        let profile = Profile {
+            current_basal: 0.5,
+            latitude: Some(78.0),
+            longitude: Some(15.0),
+            basal_profile: vec![
+                BasalScheduleEntry::new(0, 0.8, 0),
+                BasalScheduleEntry::with_solar_anchor(
+                    1,
+                    1.2,
+                    SolarAnchor { event: SolarEvent::Sunrise, offset_minutes: 0 },
+                    360,
+                ),
+            ],
+            ..Default::default()
+        };
+
+        // Deep winter above the Arctic Circle: sunrise doesn't occur, so
+        // the fallback minute-of-day (06:00) applies.
+        let time = Utc.with_ymd_and_hms(2024, 12, 21, 6, 30, 0).unwrap();
+        assert!((basal_lookup(&profile, time) - 1.2).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_basal_segments_normalizes_schedule() {
+        let profile = make_profile_with_schedule();
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
+
+        let segments = basal_segments(&profile, time);
+        assert_eq!(
+            segments,
+            vec![
+                (0, 360, 0.8),
+                (360, 720, 1.0),
+                (720, 1080, 1.2),
+                (1080, 1440, 0.9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_basal_segments_empty_schedule_is_current_basal_all_day() {
+        let profile = Profile {
+            current_basal: 0.75,
+            basal_profile: vec![],
+            ..Default::default()
+        };
+
+        let segments = basal_segments(&profile, Utc::now());
+        assert_eq!(segments, vec![(0, 1440, 0.75)]);
+    }
+
+    #[test]
+    fn test_total_daily_basal_integrates_schedule() {
+        let profile = make_profile_with_schedule();
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
+
+        // 6h * 0.8 + 6h * 1.0 + 6h * 1.2 + 6h * 0.9 = 23.4
+        let total = total_daily_basal(&profile, time);
+        assert!((total - 23.4).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_total_daily_basal_empty_schedule_uses_current_basal() {
+        let profile = Profile {
+            current_basal: 0.75,
+            basal_profile: vec![],
+            ..Default::default()
+        };
+
+        let total = total_daily_basal(&profile, Utc::now());
+        assert!((total - 18.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_compile_basal_schedule_matches_basal_lookup_across_the_day() {
+        let profile = make_profile_with_schedule();
+        let day = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let compiled = compile_basal_schedule(&profile, day);
+
+        for minute in (0..1440u32).step_by(37) {
+            let time = day + chrono::Duration::minutes(minute as i64);
+            let rate = (compiled.lookup(minute) * 1000.0).round() / 1000.0;
+            assert_eq!(rate, basal_lookup(&profile, time));
+        }
+    }
+
+    #[test]
+    fn test_recurring_override_raises_scheduled_basal() {
+        use crate::profile::overrides::{RecurrenceFreq, RecurrenceRule, ScheduleOverride};
+
+        let mut profile = make_profile_with_schedule();
+        profile.schedule_overrides = vec![ScheduleOverride {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap(),
+            duration_minutes: 180,
+            recurrence: RecurrenceRule {
+                freq: RecurrenceFreq::Daily,
+                interval: 1,
+                by_day: vec![],
+                count: None,
+                until: None,
+            },
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        }];
+
+        // Inside the override window: 1.0 (scheduled at 08:00) * 1.2
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
+        assert!((basal_lookup(&profile, time) - 1.2).abs() < 0.001);
+
+        // Outside the window: falls back to the plain schedule
+        let evening = Utc.with_ymd_and_hms(2024, 1, 1, 20, 0, 0).unwrap();
+        assert!((basal_lookup(&profile, evening) - 0.9).abs() < 0.001);
+    }
 }
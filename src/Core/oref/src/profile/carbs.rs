@@ -1,43 +1,75 @@
 //! Carb ratio schedule lookups
 
-use chrono::{DateTime, Timelike, Utc};
-use crate::types::Profile;
+use chrono::{DateTime, Datelike, Utc};
+use crate::iob::history::local_minutes_of_day;
+use crate::profile::schedule::CompiledSchedule;
+use crate::profile::{overrides, solar};
+use crate::types::{CarbRatioScheduleEntry, Profile};
 
-/// Look up the carb ratio at a specific time
+/// Look up the carb ratio at a specific time, using the weekday-specific
+/// schedule mapped for `time`'s weekday when the profile defines one,
+/// falling back to the default `carb_ratio_profile` otherwise. Any
+/// solar-anchored entries are resolved against `time`'s date first, and
+/// any active recurring `schedule_overrides` entry takes precedence over
+/// the resulting scheduled ratio.
+///
+/// This is a thin wrapper around [`compile_carb_ratio_schedule`] for
+/// one-off lookups; callers evaluating many timestamps within the same
+/// day or week should compile once and reuse the result instead.
 pub fn carb_ratio_lookup(profile: &Profile, time: DateTime<Utc>) -> f64 {
-    // If no schedule defined, return the single carb ratio
-    if profile.carb_ratio_profile.is_empty() {
-        return profile.carb_ratio;
-    }
+    let compiled = compile_carb_ratio_schedule(profile, time);
+    let minute_of_day = local_minutes_of_day(profile, time.timestamp_millis());
+    let scheduled = compiled.lookup(minute_of_day);
 
-    let now_minutes = time.hour() * 60 + time.minute();
-
-    // Sort by index
-    let mut schedule: Vec<_> = profile.carb_ratio_profile.iter().collect();
-    schedule.sort_by_key(|e| e.i);
-
-    // Default to last entry (wraps around midnight)
-    let mut ratio = schedule
-        .last()
-        .map(|e| e.ratio)
-        .unwrap_or(profile.carb_ratio);
-
-    // Find the matching time window
-    for i in 0..schedule.len() {
-        let entry = schedule[i];
-        let next_minutes = if i + 1 < schedule.len() {
-            schedule[i + 1].minutes
-        } else {
-            24 * 60 // End of day
-        };
+    overrides::active_override(&profile.schedule_overrides, time)
+        .and_then(|o| o.apply_carb_ratio(scheduled, time))
+        .unwrap_or(scheduled)
+}
 
-        if now_minutes >= entry.minutes && now_minutes < next_minutes {
-            ratio = entry.ratio;
-            break;
-        }
-    }
+/// Compile the carb ratio schedule active on `time`'s weekday -- with
+/// solar anchors resolved against `time`'s date -- into a
+/// [`CompiledSchedule`], so a simulation or replay loop evaluating many
+/// timestamps across that same day can look each one up with
+/// `CompiledSchedule::lookup` instead of re-sorting the schedule on
+/// every call the way `carb_ratio_lookup` does. Recurring
+/// `schedule_overrides` still need to be applied per timestamp by the
+/// caller, since they aren't a function of minute-of-day alone.
+pub fn compile_carb_ratio_schedule(profile: &Profile, time: DateTime<Utc>) -> CompiledSchedule {
+    let entries = profile
+        .weekday_schedules
+        .as_ref()
+        .and_then(|schedules| schedules.for_weekday(time.weekday()))
+        .map(|day| &day.carb_ratio_profile)
+        .unwrap_or(&profile.carb_ratio_profile);
+
+    let resolved = resolve_solar_entries(profile, entries, time);
+    CompiledSchedule::compile(&resolved, profile.carb_ratio, |e| e.ratio)
+}
 
-    ratio
+/// Resolve any `solar_anchor` boundaries in `entries` against `time`'s
+/// date and the profile's `latitude`/`longitude`, falling back to the
+/// entry's plain `minutes` when the profile has no location set or the
+/// event doesn't occur that day (polar day/night). Entries without a
+/// solar anchor are returned unchanged.
+fn resolve_solar_entries(
+    profile: &Profile,
+    entries: &[CarbRatioScheduleEntry],
+    time: DateTime<Utc>,
+) -> Vec<CarbRatioScheduleEntry> {
+    entries
+        .iter()
+        .map(|entry| {
+            let mut resolved = entry.clone();
+            if let Some(anchor) = &entry.solar_anchor {
+                if let (Some(lat), Some(lon)) = (profile.latitude, profile.longitude) {
+                    if let Some(minutes) = solar::resolve_minutes_of_day(anchor, lat, lon, time) {
+                        resolved.minutes = minutes.clamp(0, 1439);
+                    }
+                }
+            }
+            resolved
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -148,4 +180,124 @@ mod tests {
         let time = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
         assert!((carb_ratio_lookup(&profile, time) - 10.0).abs() < 0.1);
     }
+
+    #[test]
+    fn test_weekday_schedule_overrides_default() {
+        use crate::types::{DaySchedule, WeekdaySchedules};
+        use std::collections::HashMap;
+
+        let mut profile = Profile {
+            carb_ratio: 10.0,
+            carb_ratio_profile: vec![
+                CarbRatioScheduleEntry::new(0, 8.0, 0),
+            ],
+            ..Default::default()
+        };
+
+        let weekend_schedule = DaySchedule {
+            basal_profile: vec![],
+            carb_ratio_profile: vec![CarbRatioScheduleEntry::new(0, 20.0, 0)],
+        };
+
+        let mut schedules = HashMap::new();
+        schedules.insert("weekend".to_string(), weekend_schedule);
+        let mut weekday_schedule = HashMap::new();
+        weekday_schedule.insert(5, "weekend".to_string()); // Saturday
+        weekday_schedule.insert(6, "weekend".to_string()); // Sunday
+
+        profile.weekday_schedules = Some(WeekdaySchedules { schedules, weekday_schedule });
+
+        // Saturday, 2024-01-06 - should use the weekend schedule
+        let saturday = Utc.with_ymd_and_hms(2024, 1, 6, 8, 0, 0).unwrap();
+        assert!((carb_ratio_lookup(&profile, saturday) - 20.0).abs() < 0.1);
+
+        // Monday has no weekend mapping - still uses the default schedule
+        let monday = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
+        assert!((carb_ratio_lookup(&profile, monday) - 8.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_solar_anchored_entry_tracks_sunset() {
+        use crate::profile::solar::{SolarAnchor, SolarEvent};
+
+        let profile = Profile {
+            carb_ratio: 10.0,
+            latitude: Some(0.0),
+            longitude: Some(0.0),
+            carb_ratio_profile: vec![
+                CarbRatioScheduleEntry::new(0, 12.0, 0),
+                CarbRatioScheduleEntry::with_solar_anchor(
+                    1,
+                    8.0,
+                    SolarAnchor { event: SolarEvent::Sunset, offset_minutes: -60 },
+                    1080,
+                ),
+            ],
+            ..Default::default()
+        };
+
+        // Equinox at the equator: sunset lands near 18:00 UTC, so "1h
+        // before sunset" should put the boundary around 17:00.
+        let time = Utc.with_ymd_and_hms(2024, 3, 20, 17, 15, 0).unwrap();
+        assert!((carb_ratio_lookup(&profile, time) - 8.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_compile_carb_ratio_schedule_matches_carb_ratio_lookup_across_the_day() {
+        let profile = Profile {
+            carb_ratio: 10.0,
+            carb_ratio_profile: vec![
+                CarbRatioScheduleEntry::new(0, 8.0, 0),
+                CarbRatioScheduleEntry::new(1, 10.0, 360),
+                CarbRatioScheduleEntry::new(2, 12.0, 720),
+                CarbRatioScheduleEntry::new(3, 9.0, 1080),
+            ],
+            ..Default::default()
+        };
+        let day = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let compiled = compile_carb_ratio_schedule(&profile, day);
+
+        for minute in (0..1440u32).step_by(37) {
+            let time = day + chrono::Duration::minutes(minute as i64);
+            assert_eq!(compiled.lookup(minute), carb_ratio_lookup(&profile, time));
+        }
+    }
+
+    #[test]
+    fn test_recurring_override_sets_absolute_carb_ratio() {
+        use crate::profile::overrides::{RecurrenceFreq, RecurrenceRule, ScheduleOverride};
+
+        let mut profile = Profile {
+            carb_ratio: 10.0,
+            carb_ratio_profile: vec![CarbRatioScheduleEntry::new(0, 10.0, 0)],
+            ..Default::default()
+        };
+
+        profile.schedule_overrides = vec![ScheduleOverride {
+            start: Utc.with_ymd_and_hms(2024, 1, 1, 17, 0, 0).unwrap(),
+            duration_minutes: 120,
+            recurrence: RecurrenceRule {
+                freq: RecurrenceFreq::Weekly,
+                interval: 1,
+                by_day: vec![],
+                count: None,
+                until: None,
+            },
+            basal_rate: None,
+            carb_ratio: Some(6.0),
+            percentage: None,
+        }];
+
+        // Inside the override window on the anchor weekday
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 17, 30, 0).unwrap();
+        assert!((carb_ratio_lookup(&profile, time) - 6.0).abs() < 0.1);
+
+        // A week later, same weekday and time - recurs
+        let next_week = Utc.with_ymd_and_hms(2024, 1, 8, 17, 30, 0).unwrap();
+        assert!((carb_ratio_lookup(&profile, next_week) - 6.0).abs() < 0.1);
+
+        // Different weekday - falls back to the plain schedule
+        let tuesday = Utc.with_ymd_and_hms(2024, 1, 2, 17, 30, 0).unwrap();
+        assert!((carb_ratio_lookup(&profile, tuesday) - 10.0).abs() < 0.1);
+    }
 }
@@ -0,0 +1,219 @@
+//! Terminal chart rendering for basal/carb-ratio schedules
+//!
+//! Quantizes a day into fixed-width columns and renders each column as a
+//! block-height bar scaled against the day's peak rate, mirroring the
+//! hour-block bar charts in time-tracking tools. Lets a user eyeball
+//! their circadian basal/carb-ratio pattern, and spot a misconfigured
+//! midnight-wrap segment, without plotting software.
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use crate::profile::basal::{basal_lookup, max_daily_basal};
+use crate::profile::carbs::carb_ratio_lookup;
+use crate::types::Profile;
+
+/// Unicode block glyphs used for bar height, from empty to full.
+const BLOCKS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Number of columns the day is split into: 48 columns is 30-minute
+/// resolution, matching the half-hour granularity most basal schedules
+/// are edited at.
+pub const CHART_COLUMNS: usize = 48;
+
+/// Which schedule a chart renders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartSchedule {
+    Basal,
+    CarbRatio,
+}
+
+impl ChartSchedule {
+    fn label(self) -> &'static str {
+        match self {
+            ChartSchedule::Basal => "basal",
+            ChartSchedule::CarbRatio => "carb ratio",
+        }
+    }
+}
+
+/// Render `profile`'s basal or carb-ratio schedule, active on `date`'s
+/// weekday, as a single-row block chart: `CHART_COLUMNS` columns
+/// spanning the day, each column's glyph height proportional to its rate
+/// relative to the day's peak (`max_daily_basal` for basal; the max
+/// sampled rate for carb ratio, which has no equivalent helper). Pass
+/// `ansi: true` to wrap each glyph in a green/yellow/red ANSI color
+/// ramp (low to high rate relative to the peak); `false` renders plain
+/// glyphs only, safe for logs. The row is prefixed with a right-aligned
+/// label naming the schedule.
+pub fn render_schedule_chart(
+    profile: &Profile,
+    schedule: ChartSchedule,
+    date: DateTime<Utc>,
+    ansi: bool,
+) -> String {
+    let day_start = Utc
+        .with_ymd_and_hms(date.year(), date.month(), date.day(), 0, 0, 0)
+        .unwrap();
+
+    let step_minutes = 24 * 60 / CHART_COLUMNS as i64;
+
+    let samples: Vec<f64> = (0..CHART_COLUMNS)
+        .map(|i| {
+            let t = day_start + Duration::minutes(step_minutes * i as i64);
+            match schedule {
+                ChartSchedule::Basal => basal_lookup(profile, t),
+                ChartSchedule::CarbRatio => carb_ratio_lookup(profile, t),
+            }
+        })
+        .collect();
+
+    let sampled_peak = samples.iter().cloned().fold(0.0_f64, f64::max);
+    let peak = match schedule {
+        ChartSchedule::Basal => max_daily_basal(profile, day_start).max(sampled_peak),
+        ChartSchedule::CarbRatio => sampled_peak,
+    };
+
+    let mut bar = String::with_capacity(CHART_COLUMNS);
+    for rate in &samples {
+        let glyph = block_for(*rate, peak);
+        if ansi {
+            bar.push_str(&ansi_wrap(glyph, *rate, peak));
+        } else {
+            bar.push(glyph);
+        }
+    }
+
+    format!("{:>10} {bar}", schedule.label())
+}
+
+/// Render both the basal and carb-ratio schedules for `date` as stacked
+/// chart rows, one per line, so the two can be eyeballed side by side.
+pub fn render_schedule_charts(profile: &Profile, date: DateTime<Utc>, ansi: bool) -> String {
+    let basal = render_schedule_chart(profile, ChartSchedule::Basal, date, ansi);
+    let carbs = render_schedule_chart(profile, ChartSchedule::CarbRatio, date, ansi);
+    format!("{basal}\n{carbs}")
+}
+
+/// Map `rate` to a block glyph, scaled by its fraction of `peak`. A
+/// non-positive peak (no schedule, or an all-zero one) always renders
+/// the empty glyph rather than dividing by zero.
+fn block_for(rate: f64, peak: f64) -> char {
+    if peak <= 0.0 {
+        return BLOCKS[0];
+    }
+    let fraction = (rate / peak).clamp(0.0, 1.0);
+    let level = (fraction * (BLOCKS.len() - 1) as f64).round() as usize;
+    BLOCKS[level]
+}
+
+/// Wrap `glyph` in an ANSI color escape, green at the low end of the
+/// day's range, through yellow, to red at the peak.
+fn ansi_wrap(glyph: char, rate: f64, peak: f64) -> String {
+    if peak <= 0.0 {
+        return glyph.to_string();
+    }
+    let fraction = (rate / peak).clamp(0.0, 1.0);
+    let code = if fraction < 0.34 {
+        32 // green
+    } else if fraction < 0.67 {
+        33 // yellow
+    } else {
+        31 // red
+    };
+    format!("\x1b[{code}m{glyph}\x1b[0m")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::BasalScheduleEntry;
+
+    fn make_profile_with_schedule() -> Profile {
+        Profile {
+            current_basal: 1.0,
+            basal_profile: vec![
+                BasalScheduleEntry::new(0, 0.8, 0),    // 00:00
+                BasalScheduleEntry::new(1, 1.0, 360),  // 06:00
+                BasalScheduleEntry::new(2, 1.2, 720),  // 12:00
+                BasalScheduleEntry::new(3, 0.9, 1080), // 18:00
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_chart_has_one_glyph_per_column() {
+        let profile = make_profile_with_schedule();
+        let chart = render_schedule_chart(
+            &profile,
+            ChartSchedule::Basal,
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            false,
+        );
+
+        let bar = chart.split_whitespace().last().unwrap();
+        assert_eq!(bar.chars().count(), CHART_COLUMNS);
+    }
+
+    #[test]
+    fn test_chart_peak_column_is_full_block() {
+        let profile = make_profile_with_schedule();
+        let chart = render_schedule_chart(
+            &profile,
+            ChartSchedule::Basal,
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            false,
+        );
+
+        // Noon (column 24) is the 1.2 U/hr peak, the schedule's max
+        let bar = chart.split_whitespace().last().unwrap();
+        let noon_column = bar.chars().nth(24).unwrap();
+        assert_eq!(noon_column, '█');
+    }
+
+    #[test]
+    fn test_chart_empty_schedule_is_flat() {
+        let profile = Profile {
+            current_basal: 0.5,
+            basal_profile: vec![],
+            ..Default::default()
+        };
+
+        let chart = render_schedule_chart(
+            &profile,
+            ChartSchedule::Basal,
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            false,
+        );
+
+        // A flat schedule sits at 100% of its own peak everywhere
+        let bar = chart.split_whitespace().last().unwrap();
+        assert!(bar.chars().all(|c| c == '█'));
+    }
+
+    #[test]
+    fn test_chart_ansi_wraps_each_glyph_in_escape_codes() {
+        let profile = make_profile_with_schedule();
+        let chart = render_schedule_chart(
+            &profile,
+            ChartSchedule::Basal,
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            true,
+        );
+
+        assert_eq!(chart.matches("\x1b[").count(), CHART_COLUMNS * 2);
+    }
+
+    #[test]
+    fn test_render_schedule_charts_stacks_both_rows() {
+        let profile = make_profile_with_schedule();
+        let combined = render_schedule_charts(
+            &profile,
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            false,
+        );
+
+        assert_eq!(combined.lines().count(), 2);
+        assert!(combined.contains("basal"));
+        assert!(combined.contains("carb ratio"));
+    }
+}
@@ -0,0 +1,58 @@
+//! Insulin sensitivity factor schedule lookups
+
+use chrono::{DateTime, Utc};
+use crate::profile::schedule;
+use crate::types::Profile;
+
+/// Look up the insulin sensitivity factor at a specific time
+pub fn isf_lookup(profile: &Profile, time: DateTime<Utc>) -> f64 {
+    schedule::lookup_at(&profile.isf_profile.sensitivities, profile, time, profile.sens, |e| e.sensitivity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use crate::types::ISFEntry;
+    use std::cell::Cell;
+
+    fn entry(offset: u32, sensitivity: f64) -> ISFEntry {
+        ISFEntry {
+            offset,
+            sensitivity,
+            end_offset: Cell::new(None),
+        }
+    }
+
+    #[test]
+    fn test_isf_lookup_no_schedule() {
+        let profile = Profile {
+            sens: 50.0,
+            ..Default::default()
+        };
+
+        let ratio = isf_lookup(&profile, Utc::now());
+        assert!((ratio - 50.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_isf_lookup_multiple_entries() {
+        let profile = Profile {
+            sens: 50.0,
+            isf_profile: crate::types::ISFProfile {
+                sensitivities: vec![
+                    entry(0, 40.0),   // 00:00
+                    entry(360, 50.0), // 06:00
+                    entry(720, 60.0), // 12:00
+                ],
+            },
+            ..Default::default()
+        };
+
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
+        assert!((isf_lookup(&profile, time) - 50.0).abs() < 0.1);
+
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 14, 0, 0).unwrap();
+        assert!((isf_lookup(&profile, time) - 60.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,562 @@
+//! Recurring profile overrides: the Nightscout "profile switch"/"temp
+//! override" use case, layered on top of `basal_lookup`/`carb_ratio_lookup`
+//! without mutating the underlying schedule.
+//!
+//! Each override carries an iCalendar-style recurrence rule
+//! (`FREQ`/`INTERVAL`/`BYDAY`/`COUNT`/`UNTIL`) so things like "every
+//! weekday 6-9am raise basal 20%" can be expressed directly.
+
+use chrono::{DateTime, Datelike, Duration, Utc};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Recurrence cadence, mirroring iCalendar's `FREQ`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecurrenceFreq {
+    Daily,
+    Weekly,
+}
+
+fn default_interval() -> u32 {
+    1
+}
+
+/// An iCalendar-style recurrence rule.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RecurrenceRule {
+    pub freq: RecurrenceFreq,
+
+    /// Repeat every `interval` days (DAILY) or weeks (WEEKLY); `0` is
+    /// treated as `1`.
+    #[cfg_attr(feature = "serde", serde(default = "default_interval"))]
+    pub interval: u32,
+
+    /// Weekdays the recurrence falls on, `0` (Monday) through `6`
+    /// (Sunday), matching `Weekday::num_days_from_monday`. Empty means
+    /// "no extra restriction" for DAILY, or "the same weekday as `start`"
+    /// for WEEKLY - i.e. the iCalendar default when `BYDAY` is omitted.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub by_day: Vec<u8>,
+
+    /// Stop after this many occurrences
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub count: Option<u32>,
+
+    /// Stop once an occurrence would start after this instant
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// A time-bounded, recurring override of the basal rate and/or carb
+/// ratio otherwise returned by `basal_lookup`/`carb_ratio_lookup`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ScheduleOverride {
+    /// The first occurrence's start time; later occurrences repeat at
+    /// the same time-of-day per `recurrence`.
+    pub start: DateTime<Utc>,
+
+    /// How long each occurrence lasts, in minutes
+    pub duration_minutes: u32,
+
+    pub recurrence: RecurrenceRule,
+
+    /// Absolute basal rate (U/hr) while the override is active
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub basal_rate: Option<f64>,
+
+    /// Absolute carb ratio (g/U) while the override is active
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub carb_ratio: Option<f64>,
+
+    /// Percentage multiplier (e.g. `1.2` for +20%) applied to the
+    /// scheduled value, used when `basal_rate`/`carb_ratio` aren't set
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub percentage: Option<f64>,
+}
+
+impl ScheduleOverride {
+    /// Apply this override to a scheduled basal rate, if it's active at
+    /// `time` and defines a basal change.
+    pub fn apply_basal(&self, scheduled: f64, time: DateTime<Utc>) -> Option<f64> {
+        if !self.is_active_at(time) {
+            return None;
+        }
+
+        self.basal_rate.or_else(|| self.percentage.map(|pct| scheduled * pct))
+    }
+
+    /// Apply this override to a scheduled carb ratio, if it's active at
+    /// `time` and defines a carb-ratio change.
+    pub fn apply_carb_ratio(&self, scheduled: f64, time: DateTime<Utc>) -> Option<f64> {
+        if !self.is_active_at(time) {
+            return None;
+        }
+
+        self.carb_ratio.or_else(|| self.percentage.map(|pct| scheduled * pct))
+    }
+
+    /// Whether `time` falls inside `[start, start + duration)` of any
+    /// occurrence of this override's recurrence rule.
+    ///
+    /// Only an occurrence starting within `duration` of `time` can
+    /// possibly cover it, so this checks that small, fixed-size window of
+    /// day offsets directly via `INTERVAL`/`BYDAY` modular arithmetic,
+    /// rather than walking every day since `start` -- the cost no longer
+    /// grows with how long ago `start` was, which matters since an
+    /// override with no `UNTIL`/`COUNT` is otherwise unbounded.
+    pub fn is_active_at(&self, time: DateTime<Utc>) -> bool {
+        if time < self.start {
+            return false;
+        }
+
+        let duration = Duration::minutes(self.duration_minutes as i64);
+        let since_start_days = (time - self.start).num_days();
+        let lookback_days = duration.num_days() + 1;
+        let earliest_day_offset = (since_start_days - lookback_days).max(0);
+
+        for day_offset in earliest_day_offset..=since_start_days {
+            let occurrence_start = self.start + Duration::days(day_offset);
+
+            if let Some(until) = self.recurrence.until {
+                if occurrence_start > until {
+                    break;
+                }
+            }
+
+            if !self.interval_matches(day_offset) || !self.on_by_day(occurrence_start) {
+                continue;
+            }
+
+            if let Some(count) = self.recurrence.count {
+                if self.occurrence_number(day_offset) > count {
+                    continue;
+                }
+            }
+
+            if time >= occurrence_start && time < occurrence_start + duration {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Whether `day_offset` days after `start` is on an `INTERVAL`-spaced
+    /// occurrence day (before any `BYDAY` filtering).
+    fn interval_matches(&self, day_offset: i64) -> bool {
+        let interval = self.recurrence.interval.max(1) as i64;
+        match self.recurrence.freq {
+            RecurrenceFreq::Daily => day_offset % interval == 0,
+            RecurrenceFreq::Weekly => day_offset.div_euclid(7) % interval == 0,
+        }
+    }
+
+    fn on_by_day(&self, occurrence_start: DateTime<Utc>) -> bool {
+        if self.recurrence.by_day.is_empty() {
+            return match self.recurrence.freq {
+                RecurrenceFreq::Daily => true,
+                RecurrenceFreq::Weekly => occurrence_start.weekday() == self.start.weekday(),
+            };
+        }
+
+        let weekday = occurrence_start.weekday().num_days_from_monday() as u8;
+        self.recurrence.by_day.contains(&weekday)
+    }
+
+    /// 1-indexed position of the occurrence at `day_offset` among all
+    /// occurrences from `start` up to and including it, used to evaluate
+    /// `COUNT` without re-deriving every prior occurrence.
+    ///
+    /// Only meaningful when `day_offset` itself already satisfies
+    /// `interval_matches`/`on_by_day` (the only time this is called).
+    fn occurrence_number(&self, day_offset: i64) -> u32 {
+        let interval = self.recurrence.interval.max(1) as i64;
+
+        match self.recurrence.freq {
+            RecurrenceFreq::Daily => {
+                let step = day_offset / interval;
+
+                if self.recurrence.by_day.is_empty() {
+                    return (step + 1) as u32;
+                }
+
+                // With BYDAY also filtering INTERVAL-spaced days, the
+                // weekday of `step * interval` days after `start` cycles
+                // with period `7 / gcd(interval, 7)` steps -- that's how
+                // long it takes to land back on the same weekday offset.
+                let period = 7 / gcd(interval, 7);
+                let matches_in = |from: i64, to_inclusive: i64| -> i64 {
+                    (from..=to_inclusive)
+                        .filter(|s| self.on_by_day(self.start + Duration::days(s * interval)))
+                        .count() as i64
+                };
+
+                let full_periods = step / period;
+                let matches_per_period = matches_in(0, period - 1);
+                let partial = matches_in(full_periods * period, step);
+
+                (full_periods * matches_per_period + partial) as u32
+            }
+            RecurrenceFreq::Weekly => {
+                let week = day_offset.div_euclid(7);
+                let r = day_offset.rem_euclid(7);
+                let weeks_before = week / interval;
+
+                let matches_in = |to_inclusive: i64| -> i64 {
+                    if self.recurrence.by_day.is_empty() {
+                        // Only `r == 0` (start's own weekday) ever matches.
+                        1
+                    } else {
+                        (0..=to_inclusive)
+                            .filter(|d| self.on_by_day(self.start + Duration::days(week * 7 + d)))
+                            .count() as i64
+                    }
+                };
+
+                let matches_per_week = matches_in(6);
+                let partial = matches_in(r);
+
+                (weeks_before * matches_per_week + partial) as u32
+            }
+        }
+    }
+}
+
+/// Greatest common divisor, used to find the period at which `BYDAY`
+/// filtering on top of `INTERVAL`-spaced days repeats.
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Resolve the first active override for `time`, in list order.
+pub fn active_override<'a>(
+    overrides: &'a [ScheduleOverride],
+    time: DateTime<Utc>,
+) -> Option<&'a ScheduleOverride> {
+    overrides.iter().find(|o| o.is_active_at(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn daily_rule(interval: u32) -> RecurrenceRule {
+        RecurrenceRule {
+            freq: RecurrenceFreq::Daily,
+            interval,
+            by_day: vec![],
+            count: None,
+            until: None,
+        }
+    }
+
+    #[test]
+    fn test_daily_override_active_within_window() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: daily_rule(1),
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        // Same day, inside the window
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap();
+        assert!(over.is_active_at(time));
+
+        // Same day, after the window
+        let after = Utc.with_ymd_and_hms(2024, 1, 1, 9, 30, 0).unwrap();
+        assert!(!over.is_active_at(after));
+
+        // Next day, recurs inside the window again
+        let next_day = Utc.with_ymd_and_hms(2024, 1, 2, 7, 0, 0).unwrap();
+        assert!(over.is_active_at(next_day));
+    }
+
+    #[test]
+    fn test_daily_override_before_start_is_inactive() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: daily_rule(1),
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        let before = Utc.with_ymd_and_hms(2023, 12, 31, 7, 0, 0).unwrap();
+        assert!(!over.is_active_at(before));
+    }
+
+    #[test]
+    fn test_weekly_override_with_by_day() {
+        // Start on Monday 2024-01-01
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: RecurrenceRule {
+                freq: RecurrenceFreq::Weekly,
+                interval: 1,
+                by_day: vec![0, 1, 2, 3, 4], // Mon-Fri
+                count: None,
+                until: None,
+            },
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        // Wednesday of the same week - active
+        let wednesday = Utc.with_ymd_and_hms(2024, 1, 3, 7, 0, 0).unwrap();
+        assert!(over.is_active_at(wednesday));
+
+        // Saturday of the same week - not in BYDAY
+        let saturday = Utc.with_ymd_and_hms(2024, 1, 6, 7, 0, 0).unwrap();
+        assert!(!over.is_active_at(saturday));
+    }
+
+    #[test]
+    fn test_interval_skips_weeks() {
+        // Every other week on Monday
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: RecurrenceRule {
+                freq: RecurrenceFreq::Weekly,
+                interval: 2,
+                by_day: vec![],
+                count: None,
+                until: None,
+            },
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        // Following Monday (week 2) - skipped
+        let week2 = Utc.with_ymd_and_hms(2024, 1, 8, 7, 0, 0).unwrap();
+        assert!(!over.is_active_at(week2));
+
+        // Two weeks later (week 3) - active again
+        let week3 = Utc.with_ymd_and_hms(2024, 1, 15, 7, 0, 0).unwrap();
+        assert!(over.is_active_at(week3));
+    }
+
+    #[test]
+    fn test_count_stops_recurrence() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: RecurrenceRule {
+                freq: RecurrenceFreq::Daily,
+                interval: 1,
+                by_day: vec![],
+                count: Some(2),
+                until: None,
+            },
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        // First and second occurrence active
+        assert!(over.is_active_at(Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap()));
+        assert!(over.is_active_at(Utc.with_ymd_and_hms(2024, 1, 2, 7, 0, 0).unwrap()));
+        // Third occurrence exceeds COUNT
+        assert!(!over.is_active_at(Utc.with_ymd_and_hms(2024, 1, 3, 7, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_until_stops_recurrence() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: RecurrenceRule {
+                freq: RecurrenceFreq::Daily,
+                interval: 1,
+                by_day: vec![],
+                count: None,
+                until: Some(Utc.with_ymd_and_hms(2024, 1, 2, 0, 0, 0).unwrap()),
+            },
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        assert!(over.is_active_at(Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap()));
+        assert!(!over.is_active_at(Utc.with_ymd_and_hms(2024, 1, 2, 7, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_apply_basal_uses_absolute_rate_over_percentage() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: daily_rule(1),
+            basal_rate: Some(0.5),
+            carb_ratio: None,
+            percentage: Some(2.0),
+        };
+
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap();
+        assert_eq!(over.apply_basal(1.0, time), Some(0.5));
+    }
+
+    #[test]
+    fn test_apply_basal_uses_percentage_when_no_absolute() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: daily_rule(1),
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap();
+        assert_eq!(over.apply_basal(1.0, time), Some(1.2));
+    }
+
+    #[test]
+    fn test_apply_basal_none_outside_window() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: daily_rule(1),
+            basal_rate: Some(0.5),
+            carb_ratio: None,
+            percentage: None,
+        };
+
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 20, 0, 0).unwrap();
+        assert_eq!(over.apply_basal(1.0, time), None);
+    }
+
+    #[test]
+    fn test_active_override_picks_first_match() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let overrides = vec![
+            ScheduleOverride {
+                start,
+                duration_minutes: 180,
+                recurrence: daily_rule(1),
+                basal_rate: Some(0.5),
+                carb_ratio: None,
+                percentage: None,
+            },
+            ScheduleOverride {
+                start,
+                duration_minutes: 180,
+                recurrence: daily_rule(1),
+                basal_rate: Some(0.9),
+                carb_ratio: None,
+                percentage: None,
+            },
+        ];
+
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap();
+        let active = active_override(&overrides, time).unwrap();
+        assert_eq!(active.basal_rate, Some(0.5));
+    }
+
+    #[test]
+    fn test_unbounded_override_stays_active_years_after_start() {
+        // No UNTIL/COUNT, and `time` is years past `start` -- this should
+        // resolve via modular arithmetic, not a multi-thousand-day walk.
+        let start = Utc.with_ymd_and_hms(2020, 1, 1, 6, 0, 0).unwrap();
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 180,
+            recurrence: daily_rule(1),
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        let inside = Utc.with_ymd_and_hms(2030, 6, 15, 7, 0, 0).unwrap();
+        assert!(over.is_active_at(inside));
+
+        let outside = Utc.with_ymd_and_hms(2030, 6, 15, 20, 0, 0).unwrap();
+        assert!(!over.is_active_at(outside));
+    }
+
+    #[test]
+    fn test_weekly_by_day_with_count_in_following_week() {
+        // Weekly on Mon/Wed/Fri, stop after 4 occurrences -- the 5th
+        // occurrence (the following week's Wednesday) should no longer
+        // be active, counting across the first week boundary.
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap(); // Monday
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 60,
+            recurrence: RecurrenceRule {
+                freq: RecurrenceFreq::Weekly,
+                interval: 1,
+                by_day: vec![0, 2, 4], // Mon, Wed, Fri
+                count: Some(4),
+                until: None,
+            },
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        // 4th occurrence: Mon, Wed, Fri, then the following Monday
+        let fourth = Utc.with_ymd_and_hms(2024, 1, 8, 6, 30, 0).unwrap();
+        assert!(over.is_active_at(fourth));
+
+        // 5th occurrence exceeds COUNT
+        let fifth = Utc.with_ymd_and_hms(2024, 1, 10, 6, 30, 0).unwrap();
+        assert!(!over.is_active_at(fifth));
+    }
+
+    #[test]
+    fn test_daily_by_day_with_interval_and_count() {
+        // Every other day, but only when that lands on a Monday, stop
+        // after 1 occurrence -- exercises BYDAY filtering a DAILY
+        // recurrence's INTERVAL-spaced days.
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap(); // Monday
+        let over = ScheduleOverride {
+            start,
+            duration_minutes: 60,
+            recurrence: RecurrenceRule {
+                freq: RecurrenceFreq::Daily,
+                interval: 2,
+                by_day: vec![0], // Monday
+                count: Some(1),
+                until: None,
+            },
+            basal_rate: None,
+            carb_ratio: None,
+            percentage: Some(1.2),
+        };
+
+        // Start itself is the only occurrence within COUNT
+        let first = Utc.with_ymd_and_hms(2024, 1, 1, 6, 30, 0).unwrap();
+        assert!(over.is_active_at(first));
+
+        // The next interval-aligned Monday (two weeks later) is the 2nd
+        // occurrence, which exceeds COUNT
+        let second = Utc.with_ymd_and_hms(2024, 1, 15, 6, 30, 0).unwrap();
+        assert!(!over.is_active_at(second));
+    }
+}
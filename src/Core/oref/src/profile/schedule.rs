@@ -0,0 +1,239 @@
+//! Generic segmented time-of-day schedule lookup
+//!
+//! Basal, ISF, and carb-ratio schedules are all the same shape: a list
+//! of entries keyed by minutes-from-midnight, resolved by picking the
+//! window containing the query time and wrapping past the last entry
+//! back to the start of the next day. This mirrors the `find_ratio_at_time`
+//! helper used in reference loop implementations, generalized so each
+//! schedule type doesn't reimplement its own windowing.
+
+use chrono::{DateTime, Utc};
+use crate::iob::history::local_minutes_of_day;
+use crate::types::Profile;
+
+/// An entry in a minutes-from-midnight keyed schedule
+pub trait ScheduleEntry {
+    /// Minutes from midnight this entry becomes active
+    fn minutes(&self) -> u32;
+
+    /// Index establishing schedule order, used when entries aren't
+    /// already sorted by time of day
+    fn index(&self) -> u32;
+}
+
+/// Look up the value active at `time`, resolved in `profile`'s local
+/// wall-clock time (per `Profile::timezone`, not raw UTC), falling back
+/// to `default` when `entries` is empty.
+pub fn lookup_at<T>(
+    entries: &[T],
+    profile: &Profile,
+    time: DateTime<Utc>,
+    default: f64,
+    value: impl Fn(&T) -> f64,
+) -> f64
+where
+    T: ScheduleEntry,
+{
+    if entries.is_empty() {
+        return default;
+    }
+
+    let now_minutes = local_minutes_of_day(profile, time.timestamp_millis());
+
+    let mut schedule: Vec<&T> = entries.iter().collect();
+    schedule.sort_by_key(|e| e.index());
+
+    // Default to the last entry, which wraps around midnight
+    let mut result = schedule.last().map(|e| value(e)).unwrap_or(default);
+
+    for i in 0..schedule.len() {
+        let entry = schedule[i];
+        let next_minutes = if i + 1 < schedule.len() {
+            schedule[i + 1].minutes()
+        } else {
+            24 * 60
+        };
+
+        if now_minutes >= entry.minutes() && now_minutes < next_minutes {
+            result = value(entry);
+            break;
+        }
+    }
+
+    result
+}
+
+/// A schedule compiled once from a list of entries into sorted
+/// boundary/value arrays, so repeated lookups -- one per simulated
+/// timestamp, for example -- don't re-sort the source schedule on every
+/// call the way [`lookup_at`] does. Built via [`CompiledSchedule::compile`]
+/// and queried with [`CompiledSchedule::lookup`].
+#[derive(Debug, Clone)]
+pub struct CompiledSchedule {
+    /// Sorted segment boundaries, minutes-from-midnight each segment
+    /// starts at
+    boundaries: Vec<u32>,
+
+    /// Value active from the corresponding boundary forward, parallel
+    /// to `boundaries`
+    values: Vec<f64>,
+
+    /// Returned by `lookup` when the schedule has no entries
+    default: f64,
+}
+
+impl CompiledSchedule {
+    /// Compile `entries` into a sorted boundary/value index, using the
+    /// same ordering rule as `lookup_at`: entries are sorted by
+    /// `index()`, and the last one (in that order) wraps around to
+    /// cover the end of the day.
+    pub fn compile<T: ScheduleEntry>(
+        entries: &[T],
+        default: f64,
+        value: impl Fn(&T) -> f64,
+    ) -> Self {
+        let mut sorted: Vec<&T> = entries.iter().collect();
+        sorted.sort_by_key(|e| e.index());
+
+        CompiledSchedule {
+            boundaries: sorted.iter().map(|e| e.minutes()).collect(),
+            values: sorted.iter().map(|e| value(e)).collect(),
+            default,
+        }
+    }
+
+    /// Look up the value active at `minute_of_day` (0..1440) with a
+    /// binary search over the precomputed boundaries, falling back to
+    /// `default` when the schedule is empty.
+    pub fn lookup(&self, minute_of_day: u32) -> f64 {
+        if self.boundaries.is_empty() {
+            return self.default;
+        }
+
+        // Count of boundaries at or before `minute_of_day`; 0 means
+        // we're before the first boundary, so wrap to the last segment.
+        let count = self.boundaries.partition_point(|&b| b <= minute_of_day);
+        let segment = if count == 0 { self.values.len() - 1 } else { count - 1 };
+        self.values[segment]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Entry {
+        i: u32,
+        minutes: u32,
+        value: f64,
+    }
+
+    impl ScheduleEntry for Entry {
+        fn minutes(&self) -> u32 {
+            self.minutes
+        }
+
+        fn index(&self) -> u32 {
+            self.i
+        }
+    }
+
+    #[test]
+    fn test_lookup_at_empty_uses_default() {
+        let entries: Vec<Entry> = vec![];
+        let result = lookup_at(&entries, &Profile::default(), Utc::now(), 42.0, |e| e.value);
+        assert_eq!(result, 42.0);
+    }
+
+    #[test]
+    fn test_lookup_at_picks_window() {
+        use chrono::TimeZone;
+
+        let entries = vec![
+            Entry { i: 0, minutes: 0, value: 1.0 },
+            Entry { i: 1, minutes: 360, value: 2.0 },
+            Entry { i: 2, minutes: 720, value: 3.0 },
+        ];
+
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 8, 0, 0).unwrap();
+        assert_eq!(lookup_at(&entries, &Profile::default(), time, 0.0, |e| e.value), 2.0);
+    }
+
+    #[test]
+    fn test_lookup_at_wraps_past_last_entry() {
+        use chrono::TimeZone;
+
+        let entries = vec![
+            Entry { i: 0, minutes: 0, value: 1.0 },
+            Entry { i: 1, minutes: 720, value: 2.0 },
+        ];
+
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 23, 59, 0).unwrap();
+        assert_eq!(lookup_at(&entries, &Profile::default(), time, 0.0, |e| e.value), 2.0);
+    }
+
+    #[test]
+    fn test_lookup_at_honors_profile_timezone() {
+        use chrono::TimeZone;
+
+        let entries = vec![
+            Entry { i: 0, minutes: 0, value: 1.0 },
+            Entry { i: 1, minutes: 720, value: 2.0 },
+        ];
+        let profile = Profile {
+            timezone: Some("+05:00".to_string()),
+            ..Default::default()
+        };
+
+        // 21:30 UTC is 02:30 local the next day -- still the first
+        // (midnight-wrapping) segment in local time, not the second.
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 21, 30, 0).unwrap();
+        assert_eq!(lookup_at(&entries, &profile, time, 0.0, |e| e.value), 1.0);
+    }
+
+    #[test]
+    fn test_compiled_schedule_empty_uses_default() {
+        let entries: Vec<Entry> = vec![];
+        let compiled = CompiledSchedule::compile(&entries, 42.0, |e| e.value);
+        assert_eq!(compiled.lookup(600), 42.0);
+    }
+
+    #[test]
+    fn test_compiled_schedule_matches_lookup_at_for_every_minute() {
+        use chrono::TimeZone;
+
+        let entries = vec![
+            Entry { i: 2, minutes: 720, value: 3.0 }, // added out of order
+            Entry { i: 0, minutes: 0, value: 1.0 },
+            Entry { i: 1, minutes: 360, value: 2.0 },
+        ];
+        let compiled = CompiledSchedule::compile(&entries, 0.0, |e| e.value);
+
+        for minute in 0..1440u32 {
+            let hour = (minute / 60) as u32;
+            let min = (minute % 60) as u32;
+            let time = chrono::Utc
+                .with_ymd_and_hms(2024, 1, 1, hour, min, 0)
+                .unwrap();
+            assert_eq!(
+                compiled.lookup(minute),
+                lookup_at(&entries, &Profile::default(), time, 0.0, |e| e.value),
+                "mismatch at minute {minute}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_compiled_schedule_wraps_before_first_boundary() {
+        let entries = vec![
+            Entry { i: 0, minutes: 360, value: 1.0 },
+            Entry { i: 1, minutes: 1080, value: 2.0 },
+        ];
+        let compiled = CompiledSchedule::compile(&entries, 0.0, |e| e.value);
+
+        // Before the first boundary wraps to the last entry (1080, 2.0)
+        assert_eq!(compiled.lookup(0), 2.0);
+        assert_eq!(compiled.lookup(359), 2.0);
+        assert_eq!(compiled.lookup(360), 1.0);
+    }
+}
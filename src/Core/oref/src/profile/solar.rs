@@ -0,0 +1,121 @@
+//! NOAA-style sunrise/sunset approximation for solar-anchored schedule
+//! segments (e.g. "30m before sunrise"), so dawn-phenomenon basal/carb-
+//! ratio breakpoints can track the sun instead of a fixed clock time.
+
+use chrono::{DateTime, Datelike, Utc};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The solar event a schedule segment boundary is anchored to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SolarEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// A schedule segment boundary expressed relative to a solar event, e.g.
+/// "30 minutes before sunrise" is `SolarAnchor { event: Sunrise, offset_minutes: -30 }`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SolarAnchor {
+    /// Which event the offset is relative to
+    pub event: SolarEvent,
+
+    /// Minutes after the event; negative means before it
+    pub offset_minutes: i32,
+}
+
+/// Resolve `anchor` to a minute-of-day at `latitude`/`longitude` (decimal
+/// degrees) on the date of `time`, using the standard NOAA approximation:
+/// solar declination from day-of-year, then the sunrise/sunset hour angle
+/// from declination and latitude.
+///
+/// Returns `None` on polar day/night, when `cos(H)` falls outside
+/// `[-1, 1]` and the event simply doesn't occur that day — callers should
+/// fall back to a fixed minute-of-day in that case.
+pub fn resolve_minutes_of_day(
+    anchor: &SolarAnchor,
+    latitude: f64,
+    longitude: f64,
+    time: DateTime<Utc>,
+) -> Option<u32> {
+    let n = time.ordinal() as f64;
+    let declination = 23.44_f64.to_radians() * (360.0_f64.to_radians() * (n + 284.0) / 365.0).sin();
+
+    let phi = latitude.to_radians();
+    let cos_h = ((-0.833_f64).to_radians().sin() - phi.sin() * declination.sin())
+        / (phi.cos() * declination.cos());
+
+    if !(-1.0..=1.0).contains(&cos_h) {
+        return None;
+    }
+
+    let h_degrees = cos_h.acos().to_degrees();
+
+    let event_utc_hours = match anchor.event {
+        SolarEvent::Sunrise => 12.0 - h_degrees / 15.0 - longitude / 15.0,
+        SolarEvent::Sunset => 12.0 + h_degrees / 15.0 - longitude / 15.0,
+    };
+
+    let total_minutes = event_utc_hours * 60.0 + anchor.offset_minutes as f64;
+    let wrapped = ((total_minutes % 1440.0) + 1440.0) % 1440.0;
+
+    Some(wrapped.round() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_equinox_sunrise_near_6am_at_equator() {
+        // Equinox at the equator: sunrise should land close to 06:00 UTC
+        let time = Utc.with_ymd_and_hms(2024, 3, 20, 0, 0, 0).unwrap();
+        let anchor = SolarAnchor { event: SolarEvent::Sunrise, offset_minutes: 0 };
+
+        let minutes = resolve_minutes_of_day(&anchor, 0.0, 0.0, time).unwrap();
+        assert!((minutes as i32 - 6 * 60).abs() < 15);
+    }
+
+    #[test]
+    fn test_offset_applied_before_event() {
+        let time = Utc.with_ymd_and_hms(2024, 3, 20, 0, 0, 0).unwrap();
+        let sunrise = SolarAnchor { event: SolarEvent::Sunrise, offset_minutes: 0 };
+        let before = SolarAnchor { event: SolarEvent::Sunrise, offset_minutes: -30 };
+
+        let sunrise_minutes = resolve_minutes_of_day(&sunrise, 0.0, 0.0, time).unwrap();
+        let before_minutes = resolve_minutes_of_day(&before, 0.0, 0.0, time).unwrap();
+
+        assert_eq!(before_minutes, (sunrise_minutes + 1440 - 30) % 1440);
+    }
+
+    #[test]
+    fn test_polar_night_returns_none() {
+        // Deep winter above the Arctic Circle: the sun never rises
+        let time = Utc.with_ymd_and_hms(2024, 12, 21, 0, 0, 0).unwrap();
+        let anchor = SolarAnchor { event: SolarEvent::Sunrise, offset_minutes: 0 };
+
+        assert_eq!(resolve_minutes_of_day(&anchor, 78.0, 15.0, time), None);
+    }
+
+    #[test]
+    fn test_polar_day_returns_none() {
+        // Midsummer above the Arctic Circle: the sun never sets
+        let time = Utc.with_ymd_and_hms(2024, 6, 21, 0, 0, 0).unwrap();
+        let anchor = SolarAnchor { event: SolarEvent::Sunset, offset_minutes: 0 };
+
+        assert_eq!(resolve_minutes_of_day(&anchor, 78.0, 15.0, time), None);
+    }
+
+    #[test]
+    fn test_result_always_in_range() {
+        let time = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let anchor = SolarAnchor { event: SolarEvent::Sunset, offset_minutes: 600 };
+
+        let minutes = resolve_minutes_of_day(&anchor, 45.0, -93.0, time).unwrap();
+        assert!(minutes < 1440);
+    }
+}
@@ -1,11 +1,16 @@
 //! Profile types for user settings and schedules
 
-use chrono::{NaiveTime, Timelike};
+use std::cell::Cell;
+
+use chrono::{NaiveTime, Timelike, Weekday};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 use crate::insulin::InsulinCurve;
+use crate::profile::overrides::ScheduleOverride;
+use crate::profile::schedule::ScheduleEntry;
+use crate::profile::solar::SolarAnchor;
 
 /// Main profile containing all user settings
 #[derive(Debug, Clone)]
@@ -176,6 +181,10 @@ pub struct Profile {
     #[cfg_attr(feature = "serde", serde(default = "default_half_basal_exercise_target"))]
     pub half_basal_exercise_target: f64,
 
+    /// Shift BG targets by the autosens ratio when no temp target is active
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub autosens_adjust_targets: bool,
+
     // ============ Safety Settings ============
     /// Skip setting neutral temps
     #[cfg_attr(feature = "serde", serde(default))]
@@ -211,9 +220,65 @@ pub struct Profile {
     #[cfg_attr(feature = "serde", serde(default))]
     pub model: Option<String>,
 
+    /// Custom basal delivery increment table, for pump families other
+    /// than the built-in x23/x54 handling. Steps are tried in order;
+    /// the last step should use `below_rate: f64::INFINITY` to catch all
+    /// remaining higher rates.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub basal_increments: Option<Vec<BasalIncrementStep>>,
+
     /// Output units (mg/dL or mmol/L)
     #[cfg_attr(feature = "serde", serde(default))]
     pub out_units: Option<String>,
+
+    /// Timezone the basal/ISF/carb-ratio schedules are defined in, as a
+    /// fixed UTC offset (e.g. "+02:00", "-0530", "UTC"/"Z"). IANA names
+    /// (e.g. "America/New_York") are NOT supported -- there's no tz
+    /// database dependency in this build to resolve their DST-aware
+    /// offset, and silently treating one as a fixed offset would be
+    /// wrong for at least half the year. An unrecognized string resolves
+    /// against UTC, same as when this is absent.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub timezone: Option<String>,
+
+    // ============ Dynamic ISF TDD Input ============
+    /// Total daily dose data for Dynamic ISF's `effective_tdd` blend
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tdd: Option<TotalDailyDose>,
+
+    // ============ Safety Caps ============
+    /// Multiplier applied to `max_daily_basal` to derive a safe basal cap
+    #[cfg_attr(feature = "serde", serde(default = "default_max_daily_safety_multiplier"))]
+    pub max_daily_safety_multiplier: f64,
+
+    /// Multiplier applied to `current_basal` to derive a safe basal cap
+    #[cfg_attr(feature = "serde", serde(default = "default_current_basal_safety_multiplier"))]
+    pub current_basal_safety_multiplier: f64,
+
+    /// Weekday-specific basal/carb-ratio schedule overrides (e.g. a
+    /// "weekend" schedule for Saturday/Sunday). Weekdays with no mapping
+    /// keep using `basal_profile`/`carb_ratio_profile` above.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub weekday_schedules: Option<WeekdaySchedules>,
+
+    // ============ Solar-Anchored Segments ============
+    /// Latitude (decimal degrees), required to resolve any
+    /// `solar_anchor` schedule boundaries
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub latitude: Option<f64>,
+
+    /// Longitude (decimal degrees), required to resolve any
+    /// `solar_anchor` schedule boundaries
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub longitude: Option<f64>,
+
+    // ============ Recurring Overrides ============
+    /// Time-bounded, recurring basal/carb-ratio overrides (the
+    /// Nightscout "profile switch"/"temp override" use case). The first
+    /// override active at lookup time wins; an empty list leaves
+    /// `basal_lookup`/`carb_ratio_lookup` unaffected.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub schedule_overrides: Vec<ScheduleOverride>,
 }
 
 // Default value functions for serde
@@ -233,6 +298,8 @@ fn default_adjustment_factor_sigmoid() -> f64 { 0.5 }
 fn default_weight_percentage() -> f64 { 0.65 }
 fn default_half_basal_exercise_target() -> f64 { 160.0 }
 fn default_true() -> bool { true }
+fn default_max_daily_safety_multiplier() -> f64 { 3.0 }
+fn default_current_basal_safety_multiplier() -> f64 { 4.0 }
 
 impl Default for Profile {
     fn default() -> Self {
@@ -279,6 +346,7 @@ impl Default for Profile {
             low_temptarget_lowers_sensitivity: false,
             exercise_mode: false,
             half_basal_exercise_target: 160.0,
+            autosens_adjust_targets: false,
             skip_neutral_temps: false,
             rewind_resets_autosens: true,
             a52_risk_enable: false,
@@ -287,7 +355,16 @@ impl Default for Profile {
             isf_profile: ISFProfile::default(),
             carb_ratio_profile: vec![],
             model: None,
+            basal_increments: None,
             out_units: None,
+            timezone: None,
+            tdd: None,
+            max_daily_safety_multiplier: 3.0,
+            current_basal_safety_multiplier: 4.0,
+            weekday_schedules: None,
+            latitude: None,
+            longitude: None,
+            schedule_overrides: vec![],
         }
     }
 }
@@ -319,6 +396,258 @@ impl Profile {
             InsulinCurve::RapidActing | InsulinCurve::UltraRapid => self.dia.max(5.0),
         }
     }
+
+    /// Round a basal rate to an increment the pump can actually deliver.
+    ///
+    /// Uses `basal_increments` when the profile supplies one, so pump
+    /// families other than x23/x54 can be represented; otherwise falls
+    /// back to the Medtronic x23/x54-style table (0.025 U/hr below 1
+    /// U/hr, 0.05 U/hr below 10 U/hr, 0.1 U/hr at or above that) for
+    /// those models, or a flat 0.05 U/hr increment for everything else.
+    /// Rounding is half-to-even within the selected increment, and a
+    /// nonzero rate is never rounded down to 0.
+    pub fn round_basal(&self, rate: f64) -> f64 {
+        let increment = self.basal_increment_for(rate);
+
+        let mut rounded = round_half_even(rate / increment) * increment;
+
+        if rate != 0.0 && rounded == 0.0 {
+            rounded = increment;
+        }
+
+        rounded
+    }
+
+    /// Resolve the delivery increment (U/hr) that applies at `rate`.
+    fn basal_increment_for(&self, rate: f64) -> f64 {
+        if let Some(table) = &self.basal_increments {
+            return table
+                .iter()
+                .find(|step| rate < step.below_rate)
+                .or_else(|| table.last())
+                .map(|step| step.increment)
+                .unwrap_or(0.05);
+        }
+
+        let is_x23_x54 = matches!(
+            self.model.as_deref(),
+            Some(m) if matches!(m, "523" | "723" | "554" | "754")
+        );
+
+        if !is_x23_x54 {
+            return 0.05;
+        }
+
+        if rate < 1.0 {
+            0.025
+        } else if rate < 10.0 {
+            0.05
+        } else {
+            0.1
+        }
+    }
+
+    /// Look up the scheduled basal rate active at a given time of day.
+    ///
+    /// Picks the entry with the greatest `minutes` at or before `t`,
+    /// wrapping to the last entry of the day when none precede it, and
+    /// falls back to `current_basal` when no schedule is set.
+    pub fn basal_at(&self, t: NaiveTime) -> f64 {
+        let minutes = t.hour() * 60 + t.minute();
+        match floor_entry_index(&self.basal_profile, minutes, |e| e.minutes) {
+            Some(i) => self.basal_profile[i].rate,
+            None => self.current_basal,
+        }
+    }
+
+    /// Look up the insulin sensitivity factor active at a given time of
+    /// day, caching each entry's resolved end offset on first lookup.
+    ///
+    /// Falls back to the scalar `sens` when no ISF schedule is set.
+    pub fn sensitivity_at(&self, t: NaiveTime) -> f64 {
+        let minutes = t.hour() * 60 + t.minute();
+        let entries = &self.isf_profile.sensitivities;
+
+        // An entry's cached [offset, end_offset) window (wrapping past
+        // midnight for the last entry of the day) bounds the lookup
+        // without re-scanning the schedule, so check already-resolved
+        // entries before falling back to the full scan below.
+        for entry in entries {
+            if let Some(end) = entry.end_offset.get() {
+                let in_window = if end <= entry.offset {
+                    minutes >= entry.offset || minutes < end
+                } else {
+                    minutes >= entry.offset && minutes < end
+                };
+                if in_window {
+                    return entry.sensitivity;
+                }
+            }
+        }
+
+        match floor_entry_index(entries, minutes, |e| e.offset) {
+            Some(i) => {
+                let entry = &entries[i];
+                if entry.end_offset.get().is_none() {
+                    // The entry with no later offset is the last of the
+                    // day, so it wraps to the *earliest* entry's offset
+                    // (tomorrow's first segment), not its own -- falling
+                    // back to `entry.offset` here would make the window
+                    // check below treat every minute of the day as
+                    // within this entry's range.
+                    let next_offset = entries
+                        .iter()
+                        .map(|e| e.offset)
+                        .filter(|&o| o > entry.offset)
+                        .min()
+                        .or_else(|| entries.iter().map(|e| e.offset).min())
+                        .unwrap_or(entry.offset);
+                    entry.end_offset.set(Some(next_offset));
+                }
+                entry.sensitivity
+            }
+            None => self.sens,
+        }
+    }
+
+    /// Look up the carb ratio active at a given time of day, falling
+    /// back to the scalar `carb_ratio` when no schedule is set.
+    pub fn carb_ratio_at(&self, t: NaiveTime) -> f64 {
+        let minutes = t.hour() * 60 + t.minute();
+        match floor_entry_index(&self.carb_ratio_profile, minutes, |e| e.minutes) {
+            Some(i) => self.carb_ratio_profile[i].ratio,
+            None => self.carb_ratio,
+        }
+    }
+
+    /// Map an active temp target into an autosens-style sensitivity
+    /// ratio, implementing the oref0 exercise-mode curve.
+    ///
+    /// For targets above 100 mg/dL, when `high_temptarget_raises_sensitivity`
+    /// or `exercise_mode` is set, the ratio follows the half-basal curve
+    /// with `c = half_basal_exercise_target - 100`: a 160 mg/dL target
+    /// with the default 160 half-basal point yields ~0.5x insulin before
+    /// the clamp below (0.7 with the default `autosens_min`). For
+    /// targets below 100, when `low_temptarget_lowers_sensitivity` is set,
+    /// the ratio scales proportionally above 1.0 using the same `c`. The
+    /// result is clamped to `[autosens_min, autosens_max]`; 1.0 is
+    /// returned when no relevant flag applies.
+    pub fn sensitivity_ratio_for_temptarget(&self, target_bg: f64) -> f64 {
+        let c = self.half_basal_exercise_target - 100.0;
+
+        let ratio = if target_bg > 100.0
+            && (self.high_temptarget_raises_sensitivity || self.exercise_mode)
+        {
+            c / (c + target_bg - 100.0)
+        } else if target_bg < 100.0 && self.low_temptarget_lowers_sensitivity {
+            (c + (100.0 - target_bg)) / c
+        } else {
+            return 1.0;
+        };
+
+        ratio.clamp(self.autosens_min, self.autosens_max)
+    }
+
+    /// Apply an autosens ratio to this profile, returning an adjusted
+    /// clone.
+    ///
+    /// Scales `current_basal` by the ratio (rounded to a deliverable
+    /// increment via `round_basal`) and, when `autosens_adjust_targets`
+    /// is set and no temp target is active, shifts `min_bg`/`max_bg`
+    /// using the oref0 formula `round((old - 60) / ratio) + 60`, floored
+    /// at 80 mg/dL. The formula is linear, so the adjusted midpoint falls
+    /// out of the adjusted min/max without needing to be tracked
+    /// separately.
+    pub fn apply_autosens(&self, data: &AutosensData) -> Profile {
+        let mut result = self.clone();
+        result.current_basal = self.round_basal(self.current_basal * data.ratio);
+
+        if self.autosens_adjust_targets && !self.temptarget_set {
+            let shift = |bg: f64| -> f64 { (((bg - 60.0) / data.ratio).round() + 60.0).max(80.0) };
+            result.min_bg = shift(self.min_bg);
+            result.max_bg = shift(self.max_bg);
+        }
+
+        result
+    }
+
+    /// Blend the pump-extrapolated, 7-day-average, and 8-hour-weighted
+    /// TDD inputs into a single figure for Dynamic ISF, the way
+    /// AndroidAPS's DynISF does.
+    ///
+    /// Falls back to `current_basal * 24` when no `tdd` data is set.
+    /// Early in the day the pump-extrapolated TDD is unreliable, so the
+    /// blend is floored at 80% of the 7-day average whenever `hour_of_day`
+    /// is before 7 and the blend would otherwise fall below that floor.
+    pub fn effective_tdd(&self) -> f64 {
+        let Some(tdd) = &self.tdd else {
+            return self.current_basal * 24.0;
+        };
+
+        let weighted = self.weight_percentage * tdd.tdd_8h_weighted
+            + (1.0 - self.weight_percentage) * tdd.tdd_7day;
+        let blended = (weighted + tdd.tdd_24h) / 2.0;
+
+        if tdd.hour_of_day < 7 && blended < 0.8 * tdd.tdd_7day {
+            0.8 * tdd.tdd_7day
+        } else {
+            blended
+        }
+    }
+
+    /// The maximum basal rate that is safe to set as a temp basal.
+    ///
+    /// This is the standard oref0 safety cap: the smallest of the
+    /// absolute `max_basal` and the two multiplier-derived ceilings, so
+    /// callers get a single clamp instead of reimplementing it.
+    pub fn max_safe_basal(&self) -> f64 {
+        self.max_basal
+            .min(self.max_daily_safety_multiplier * self.max_daily_basal)
+            .min(self.current_basal_safety_multiplier * self.current_basal)
+    }
+}
+
+/// Find the schedule entry whose key is the greatest at-or-before
+/// `minutes`, wrapping to the entry with the greatest key overall when
+/// none precede it (i.e. we're before the first entry of the day).
+fn floor_entry_index<T>(entries: &[T], minutes: u32, key: impl Fn(&T) -> u32) -> Option<usize> {
+    if entries.is_empty() {
+        return None;
+    }
+
+    let mut best: Option<(usize, u32)> = None;
+    for (i, e) in entries.iter().enumerate() {
+        let k = key(e);
+        if k <= minutes && best.map_or(true, |(_, bk)| k >= bk) {
+            best = Some((i, k));
+        }
+    }
+
+    let (i, _) = best.unwrap_or_else(|| {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (i, key(e)))
+            .max_by_key(|&(_, k)| k)
+            .unwrap()
+    });
+
+    Some(i)
+}
+
+/// Round half-to-even (banker's rounding), since `f64::round` always
+/// rounds halves away from zero.
+fn round_half_even(x: f64) -> f64 {
+    let floor = x.floor();
+    if (x - floor - 0.5).abs() < 1e-9 {
+        if (floor as i64) % 2 == 0 {
+            floor
+        } else {
+            floor + 1.0
+        }
+    } else {
+        x.round()
+    }
 }
 
 /// Builder for Profile
@@ -383,11 +712,99 @@ impl ProfileBuilder {
         self
     }
 
+    pub fn basal_increments(mut self, increments: Vec<BasalIncrementStep>) -> Self {
+        self.profile.basal_increments = Some(increments);
+        self
+    }
+
+    pub fn tdd(mut self, tdd: TotalDailyDose) -> Self {
+        self.profile.tdd = Some(tdd);
+        self
+    }
+
+    pub fn max_daily_safety_multiplier(mut self, multiplier: f64) -> Self {
+        self.profile.max_daily_safety_multiplier = multiplier;
+        self
+    }
+
+    pub fn current_basal_safety_multiplier(mut self, multiplier: f64) -> Self {
+        self.profile.current_basal_safety_multiplier = multiplier;
+        self
+    }
+
+    pub fn weekday_schedules(mut self, schedules: WeekdaySchedules) -> Self {
+        self.profile.weekday_schedules = Some(schedules);
+        self
+    }
+
+    pub fn location(mut self, latitude: f64, longitude: f64) -> Self {
+        self.profile.latitude = Some(latitude);
+        self.profile.longitude = Some(longitude);
+        self
+    }
+
+    pub fn schedule_overrides(mut self, overrides: Vec<ScheduleOverride>) -> Self {
+        self.profile.schedule_overrides = overrides;
+        self
+    }
+
     pub fn build(self) -> Profile {
         self.profile
     }
 }
 
+/// One step of a configurable basal delivery increment table
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BasalIncrementStep {
+    /// Rates below this threshold (U/hr) round to `increment`
+    pub below_rate: f64,
+
+    /// Rounding increment (U/hr) for this step
+    pub increment: f64,
+}
+
+/// A named daily basal/carb-ratio schedule, for weekday-specific
+/// overrides of the profile's default schedules.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DaySchedule {
+    /// Basal rate schedule for this day
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub basal_profile: Vec<BasalScheduleEntry>,
+
+    /// Carb ratio schedule for this day
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub carb_ratio_profile: Vec<CarbRatioScheduleEntry>,
+}
+
+/// Weekday-specific schedule overrides: a set of named `DaySchedule`s
+/// (e.g. "weekend") plus a mapping from day-of-week to schedule name.
+/// Borrowed from the systemd `OnCalendar`/cron weekday-mask idea, but
+/// keyed by name rather than a bitmask so the same schedule can cover
+/// several days (e.g. `Mon..Fri` sharing "weekday").
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WeekdaySchedules {
+    /// Named daily schedules, e.g. `"weekend" -> DaySchedule { .. }`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub schedules: std::collections::HashMap<String, DaySchedule>,
+
+    /// Day-of-week to schedule name, keyed by `0` (Monday) through `6`
+    /// (Sunday), matching `Weekday::num_days_from_monday`. Weekdays
+    /// absent here fall back to the profile's default schedules.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub weekday_schedule: std::collections::HashMap<u8, String>,
+}
+
+impl WeekdaySchedules {
+    /// Resolve the `DaySchedule` mapped to `weekday`, if any.
+    pub fn for_weekday(&self, weekday: Weekday) -> Option<&DaySchedule> {
+        let name = self.weekday_schedule.get(&weekday.num_days_from_monday())?;
+        self.schedules.get(name)
+    }
+}
+
 /// Entry in a basal rate schedule
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -402,8 +819,25 @@ pub struct BasalScheduleEntry {
     /// Basal rate (U/hr)
     pub rate: f64,
 
-    /// Minutes from midnight
+    /// Minutes from midnight. Used as-is unless `solar_anchor` is set and
+    /// resolves successfully, in which case it's also the fallback for
+    /// polar day/night or a profile with no `latitude`/`longitude`.
     pub minutes: u32,
+
+    /// When set, this entry's boundary tracks a solar event (e.g. "30m
+    /// before sunrise") instead of a fixed clock time
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub solar_anchor: Option<SolarAnchor>,
+}
+
+impl ScheduleEntry for BasalScheduleEntry {
+    fn minutes(&self) -> u32 {
+        self.minutes
+    }
+
+    fn index(&self) -> u32 {
+        self.i
+    }
 }
 
 impl BasalScheduleEntry {
@@ -414,6 +848,7 @@ impl BasalScheduleEntry {
             start: None,
             rate,
             minutes,
+            solar_anchor: None,
         }
     }
 
@@ -425,6 +860,20 @@ impl BasalScheduleEntry {
             start: Some(start.format("%H:%M:%S").to_string()),
             rate,
             minutes,
+            solar_anchor: None,
+        }
+    }
+
+    /// Create a solar-anchored entry. `fallback_minutes` is used when the
+    /// solar event doesn't occur that day (polar day/night) or the
+    /// profile has no `latitude`/`longitude`.
+    pub fn with_solar_anchor(i: u32, rate: f64, anchor: SolarAnchor, fallback_minutes: u32) -> Self {
+        Self {
+            i,
+            start: None,
+            rate,
+            minutes: fallback_minutes,
+            solar_anchor: Some(anchor),
         }
     }
 }
@@ -443,8 +892,25 @@ pub struct CarbRatioScheduleEntry {
     /// Carb ratio (grams per unit)
     pub ratio: f64,
 
-    /// Minutes from midnight
+    /// Minutes from midnight. Used as-is unless `solar_anchor` is set and
+    /// resolves successfully, in which case it's also the fallback for
+    /// polar day/night or a profile with no `latitude`/`longitude`.
     pub minutes: u32,
+
+    /// When set, this entry's boundary tracks a solar event (e.g. "30m
+    /// before sunrise") instead of a fixed clock time
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub solar_anchor: Option<SolarAnchor>,
+}
+
+impl ScheduleEntry for CarbRatioScheduleEntry {
+    fn minutes(&self) -> u32 {
+        self.minutes
+    }
+
+    fn index(&self) -> u32 {
+        self.i
+    }
 }
 
 impl CarbRatioScheduleEntry {
@@ -455,6 +921,7 @@ impl CarbRatioScheduleEntry {
             start: None,
             ratio,
             minutes,
+            solar_anchor: None,
         }
     }
 
@@ -466,6 +933,20 @@ impl CarbRatioScheduleEntry {
             start: Some(start.format("%H:%M:%S").to_string()),
             ratio,
             minutes,
+            solar_anchor: None,
+        }
+    }
+
+    /// Create a solar-anchored entry. `fallback_minutes` is used when the
+    /// solar event doesn't occur that day (polar day/night) or the
+    /// profile has no `latitude`/`longitude`.
+    pub fn with_solar_anchor(i: u32, ratio: f64, anchor: SolarAnchor, fallback_minutes: u32) -> Self {
+        Self {
+            i,
+            start: None,
+            ratio,
+            minutes: fallback_minutes,
+            solar_anchor: Some(anchor),
         }
     }
 }
@@ -485,7 +966,7 @@ impl ISFProfile {
             sensitivities: vec![ISFEntry {
                 offset: 0,
                 sensitivity,
-                end_offset: None,
+                end_offset: Cell::new(None),
             }],
         }
     }
@@ -501,9 +982,53 @@ pub struct ISFEntry {
     /// Sensitivity (mg/dL per unit)
     pub sensitivity: f64,
 
-    /// End offset for caching (not serialized)
+    /// End offset cache, populated lazily on first lookup so repeated
+    /// queries against the same entry don't re-scan the schedule
+    /// (not serialized)
     #[cfg_attr(feature = "serde", serde(skip))]
-    pub end_offset: Option<u32>,
+    pub end_offset: Cell<Option<u32>>,
+}
+
+/// Total daily dose inputs for the Dynamic ISF TDD blend
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct TotalDailyDose {
+    /// Pump-extrapolated TDD over the last full 24h (units)
+    pub tdd_24h: f64,
+
+    /// 7-day rolling average TDD (units)
+    pub tdd_7day: f64,
+
+    /// Rolling 8-hour-weighted TDD (units)
+    pub tdd_8h_weighted: f64,
+
+    /// Hour of day (0-23) this snapshot was taken at
+    pub hour_of_day: u32,
+}
+
+impl TotalDailyDose {
+    /// Create a new TDD snapshot
+    pub fn new(tdd_24h: f64, tdd_7day: f64, tdd_8h_weighted: f64, hour_of_day: u32) -> Self {
+        Self {
+            tdd_24h,
+            tdd_7day,
+            tdd_8h_weighted,
+            hour_of_day,
+        }
+    }
+}
+
+impl ScheduleEntry for ISFEntry {
+    fn minutes(&self) -> u32 {
+        self.offset
+    }
+
+    fn index(&self) -> u32 {
+        // ISF entries have no separate index field; they're already
+        // keyed and ordered by offset
+        self.offset
+    }
 }
 
 /// Autosens data containing sensitivity ratio
@@ -523,3 +1048,32 @@ impl AutosensData {
         Self { ratio }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sensitivity_at_out_of_order_does_not_poison_cache() {
+        let mut profile = Profile::default();
+        profile.isf_profile = ISFProfile {
+            sensitivities: vec![
+                ISFEntry { offset: 0, sensitivity: 40.0, end_offset: Cell::new(None) },
+                ISFEntry { offset: 480, sensitivity: 50.0, end_offset: Cell::new(None) },
+                ISFEntry { offset: 1020, sensitivity: 60.0, end_offset: Cell::new(None) },
+            ],
+        };
+
+        // Querying the last entry's window first used to wrap its cached
+        // end_offset back to its own offset, matching every minute of
+        // the day on every later call.
+        let late = NaiveTime::from_hms_opt(23, 0, 0).unwrap();
+        assert_eq!(profile.sensitivity_at(late), 60.0);
+
+        let morning = NaiveTime::from_hms_opt(10, 0, 0).unwrap();
+        assert_eq!(profile.sensitivity_at(morning), 50.0);
+
+        let midnight = NaiveTime::from_hms_opt(1, 0, 0).unwrap();
+        assert_eq!(profile.sensitivity_at(midnight), 40.0);
+    }
+}
@@ -1,29 +1,66 @@
 //! Time and timestamp utilities
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use crate::Result;
 use crate::OrefError;
 
+/// Epoch integers above this magnitude are assumed to be
+/// milliseconds rather than seconds (10-digit vs. 13-digit timestamps).
+/// `9_999_999_999` seconds is the year 2286, comfortably past any real
+/// treatment timestamp, so there's no realistic ambiguity in practice.
+const EPOCH_SECONDS_MAX: i64 = 9_999_999_999;
+
 /// Parse a timestamp string into a DateTime
 ///
-/// Supports multiple formats:
+/// Supports multiple formats, tried in order:
 /// - RFC3339: "2024-01-01T12:00:00Z"
+/// - RFC2822: "Mon, 1 Jan 2024 12:00:00 +0000"
+/// - ISO with fractional seconds and a numeric offset: "2024-01-01T12:00:00.123+0200"
 /// - ISO with space: "2024-01-01 12:00:00"
-/// - Unix milliseconds: "1704110400000"
+/// - Unix epoch seconds or milliseconds, disambiguated by magnitude: "1704110400" / "1704110400000"
 pub fn parse_timestamp(s: &str) -> Result<DateTime<Utc>> {
+    let s = s.trim();
+
     // Try RFC3339 first
     if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
         return Ok(dt.with_timezone(&Utc));
     }
 
-    // Try common ISO format with space
-    if let Ok(dt) = DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
+    // Try RFC2822, as sent by some Nightscout/pump feeds
+    if let Ok(dt) = DateTime::parse_from_rfc2822(s) {
         return Ok(dt.with_timezone(&Utc));
     }
 
-    // Try Unix milliseconds
-    if let Ok(millis) = s.parse::<i64>() {
-        if let Some(dt) = DateTime::from_timestamp_millis(millis) {
+    // ISO-ish formats carrying a numeric offset, with or without
+    // fractional seconds
+    for fmt in ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%z"] {
+        if let Ok(dt) = DateTime::parse_from_str(s, fmt) {
+            return Ok(dt.with_timezone(&Utc));
+        }
+    }
+
+    // Offset-less ISO formats (assumed UTC), with or without fractional
+    // seconds and with either a "T" or space date/time separator
+    for fmt in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+    ] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(s, fmt) {
+            return Ok(naive.and_utc());
+        }
+    }
+
+    // Unix epoch integer, seconds or milliseconds depending on magnitude
+    if let Ok(value) = s.parse::<i64>() {
+        let dt = if value.abs() > EPOCH_SECONDS_MAX {
+            DateTime::from_timestamp_millis(value)
+        } else {
+            DateTime::from_timestamp(value, 0)
+        };
+
+        if let Some(dt) = dt {
             return Ok(dt);
         }
     }
@@ -56,6 +93,43 @@ mod tests {
         assert_eq!(result.year(), 2024);
     }
 
+    #[test]
+    fn test_parse_epoch_seconds() {
+        // Same instant as test_parse_millis, but in seconds (10 digits)
+        let result = parse_timestamp("1704110400").unwrap();
+        assert_eq!(result.year(), 2024);
+        assert_eq!(result.hour(), 12);
+    }
+
+    #[test]
+    fn test_parse_rfc2822() {
+        let result = parse_timestamp("Mon, 1 Jan 2024 12:00:00 +0000").unwrap();
+        assert_eq!(result.year(), 2024);
+        assert_eq!(result.hour(), 12);
+    }
+
+    #[test]
+    fn test_parse_iso_with_fractional_seconds_and_offset() {
+        let result = parse_timestamp("2024-01-01T12:00:00.123+0200").unwrap();
+        assert_eq!(result.year(), 2024);
+        // +0200 normalizes to 10:00 UTC
+        assert_eq!(result.hour(), 10);
+    }
+
+    #[test]
+    fn test_parse_iso_space_with_fractional_seconds() {
+        let result = parse_timestamp("2024-01-01 12:00:00.500").unwrap();
+        assert_eq!(result.year(), 2024);
+        assert_eq!(result.hour(), 12);
+    }
+
+    #[test]
+    fn test_parse_iso_space_no_offset() {
+        let result = parse_timestamp("2024-01-01 12:00:00").unwrap();
+        assert_eq!(result.year(), 2024);
+        assert_eq!(result.hour(), 12);
+    }
+
     #[test]
     fn test_round_trip() {
         let original = Utc::now();